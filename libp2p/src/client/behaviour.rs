@@ -11,6 +11,8 @@ use libp2p_swarm::{
     THandlerOutEvent, ToSwarm, derive_prelude::ConnectionEstablished,
 };
 
+use utils::{RunParams, RunStats};
+
 use crate::client::handler::Handler;
 
 static NEXT_RUN_ID: AtomicUsize = AtomicUsize::new(1);
@@ -19,8 +21,7 @@ static NEXT_RUN_ID: AtomicUsize = AtomicUsize::new(1);
 pub struct Event {
     #[allow(unused)]
     pub id: usize,
-    #[allow(unused)]
-    pub result: Result<(), String>,
+    pub result: Result<RunStats, String>,
 }
 
 #[derive(Default)]
@@ -36,12 +37,7 @@ impl Behaviour {
         Self::default()
     }
 
-    pub fn perf(
-        &mut self,
-        server: PeerId,
-        upload_bytes: u64,
-        download_bytes: u64,
-    ) -> Result<(), NotConnected> {
+    pub fn perf(&mut self, server: PeerId, params: RunParams) -> Result<(), NotConnected> {
         if !self.connected.contains(&server) {
             return Err(NotConnected {});
         }
@@ -51,8 +47,7 @@ impl Behaviour {
             handler: NotifyHandler::Any,
             event: crate::client::handler::Command {
                 id: NEXT_RUN_ID.fetch_add(1, Ordering::SeqCst),
-                upload_bytes,
-                download_bytes,
+                params,
             },
         });
 
@@ -103,12 +98,10 @@ impl NetworkBehaviour for Behaviour {
                 peer_id,
                 connection_id: _,
                 endpoint: _,
-                remaining_established,
+                remaining_established: 0,
                 ..
             }) => {
-                if remaining_established == 0 {
-                    assert!(self.connected.remove(&peer_id));
-                }
+                assert!(self.connected.remove(&peer_id));
             }
             _ => {}
         }
@@ -1,29 +1,30 @@
 use std::{
     collections::VecDeque,
     task::{Context, Poll},
+    time::Instant,
 };
 
 use futures::{StreamExt, future::BoxFuture, stream::FuturesUnordered};
-use libp2p_core::upgrade::{DeniedUpgrade, ReadyUpgrade};
+use libp2p_core::upgrade::DeniedUpgrade;
 use libp2p_swarm::{
-    ConnectionHandler, ConnectionHandlerEvent, StreamProtocol, SubstreamProtocol,
+    ConnectionHandler, ConnectionHandlerEvent, SubstreamProtocol,
     handler::{
         ConnectionEvent, DialUpgradeError, FullyNegotiatedInbound, FullyNegotiatedOutbound,
         ListenUpgradeError,
     },
 };
+use utils::{PerfError, ProtocolVersion, RunParams, RunStats};
 
 #[derive(Debug)]
 pub struct Command {
     pub id: usize,
-    pub upload_bytes: u64,
-    pub download_bytes: u64,
+    pub params: RunParams,
 }
 
 #[derive(Debug)]
 pub struct Event {
     pub(crate) id: usize,
-    pub(crate) result: Result<(), String>,
+    pub(crate) result: Result<RunStats, String>,
 }
 
 pub struct Handler {
@@ -31,21 +32,18 @@ pub struct Handler {
     queued_events: VecDeque<
         ConnectionHandlerEvent<
             <Self as ConnectionHandler>::OutboundProtocol,
-            (),
+            <Self as ConnectionHandler>::OutboundOpenInfo,
             <Self as ConnectionHandler>::ToBehaviour,
         >,
     >,
 
-    requested_streams: VecDeque<Command>,
-
-    outbound: FuturesUnordered<BoxFuture<'static, (usize, Result<(), std::io::Error>)>>,
+    outbound: FuturesUnordered<BoxFuture<'static, (usize, Result<RunStats, PerfError>)>>,
 }
 
 impl Handler {
     pub fn new() -> Self {
         Self {
             queued_events: Default::default(),
-            requested_streams: Default::default(),
             outbound: FuturesUnordered::new(),
         }
     }
@@ -61,8 +59,9 @@ impl ConnectionHandler for Handler {
     type FromBehaviour = Command;
     type ToBehaviour = Event;
     type InboundProtocol = DeniedUpgrade;
-    type OutboundProtocol = ReadyUpgrade<StreamProtocol>;
-    type OutboundOpenInfo = ();
+    type OutboundProtocol = crate::perf::Upgrade;
+    /// The command the substream is opened for, with the time the substream was requested.
+    type OutboundOpenInfo = (Command, Instant);
     type InboundOpenInfo = ();
 
     fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
@@ -70,14 +69,11 @@ impl ConnectionHandler for Handler {
     }
 
     fn on_behaviour_event(&mut self, command: Self::FromBehaviour) {
-        self.requested_streams.push_back(command);
+        let upgrade = crate::perf::client_upgrade(command.params.protocol);
 
         self.queued_events
             .push_back(ConnectionHandlerEvent::OutboundSubstreamRequest {
-                protocol: SubstreamProtocol::new(
-                    ReadyUpgrade::new(StreamProtocol::new(crate::perf::PROTOCOL_NAME)),
-                    (),
-                ),
+                protocol: SubstreamProtocol::new(upgrade, (command, Instant::now())),
             })
     }
 
@@ -98,20 +94,32 @@ impl ConnectionHandler for Handler {
             }) => void::unreachable(protocol),
             ConnectionEvent::FullyNegotiatedOutbound(FullyNegotiatedOutbound {
                 protocol,
-                info: (),
+                info: (Command { id, params }, requested),
             }) => {
-                let Command {
-                    id,
-                    upload_bytes,
-                    download_bytes,
-                } = self
-                    .requested_streams
-                    .pop_front()
-                    .expect("opened a stream without a pending command");
-
+                let substream_setup = requested.elapsed();
+
+                let (stream, version) = protocol;
+                if version == ProtocolVersion::V1 {
+                    tracing::info!(
+                        target: crate::perf::LOG_TARGET,
+                        "server does not support {}, falling back to {}",
+                        crate::perf::PROTOCOL_NAME,
+                        crate::perf::PROTOCOL_NAME_V1
+                    );
+                }
                 let future = Box::pin(async move {
-                    let result =
-                        crate::perf::client_mode(protocol, upload_bytes, download_bytes).await;
+                    let result = utils::client_mode(
+                        stream,
+                        version,
+                        id as u64,
+                        params,
+                        crate::perf::IMPLEMENTATION,
+                    )
+                    .await
+                    .map(|stats| RunStats {
+                        substream_setup: Some(substream_setup),
+                        ..stats
+                    });
                     (id, result)
                 });
 
@@ -121,11 +129,10 @@ impl ConnectionHandler for Handler {
             ConnectionEvent::AddressChange(_)
             | ConnectionEvent::LocalProtocolsChange(_)
             | ConnectionEvent::RemoteProtocolsChange(_) => {}
-            ConnectionEvent::DialUpgradeError(DialUpgradeError { info: (), error }) => {
-                let Command { id, .. } = self
-                    .requested_streams
-                    .pop_front()
-                    .expect("requested stream without pending command");
+            ConnectionEvent::DialUpgradeError(DialUpgradeError {
+                info: (Command { id, .. }, _),
+                error,
+            }) => {
                 self.queued_events
                     .push_back(ConnectionHandlerEvent::NotifyBehaviour(Event {
                         id,
@@ -145,7 +152,9 @@ impl ConnectionHandler for Handler {
     fn poll(
         &mut self,
         cx: &mut Context<'_>,
-    ) -> Poll<ConnectionHandlerEvent<Self::OutboundProtocol, (), Self::ToBehaviour>> {
+    ) -> Poll<
+        ConnectionHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::ToBehaviour>,
+    > {
         if let Some(event) = self.queued_events.pop_front() {
             return Poll::Ready(event);
         }
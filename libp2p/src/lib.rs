@@ -0,0 +1,233 @@
+//! libp2p swarms running the perf protocols, as servers, clients or both in one process.
+
+use futures::StreamExt;
+use libp2p::{
+    Swarm, Transport,
+    identity::{Keypair, ed25519},
+};
+use libp2p_swarm::SwarmEvent;
+
+use utils::{NodeKey, RunParams, RunStats, SetupEvent, TransferOpts, TransportLayer};
+
+pub mod client;
+pub mod perf;
+pub mod server;
+pub mod setup;
+
+/// The ed25519 keypair of `key`, the same litep2p derives.
+pub fn keypair(key: &NodeKey) -> Result<Keypair, Box<dyn std::error::Error>> {
+    let keypair = ed25519::Keypair::from(ed25519::SecretKey::try_from_bytes(key.seed())?);
+    key.verify_public(&keypair.public().to_bytes())?;
+    Ok(keypair.into())
+}
+
+/// Build a swarm serving the perf protocols over TCP.
+pub fn server_swarm(
+    local_key: Keypair,
+    verify: bool,
+    transfer: TransferOpts,
+) -> Result<Swarm<server::behaviour::Behaviour>, Box<dyn std::error::Error>> {
+    let tcp_config = libp2p::tcp::Config::new().nodelay(true);
+    let swarm = libp2p::SwarmBuilder::with_existing_identity(local_key)
+        .with_tokio()
+        .with_tcp(
+            tcp_config,
+            libp2p_noise::Config::new,
+            libp2p_yamux::Config::default,
+        )?
+        .with_dns()?
+        .with_behaviour(|_key| server::behaviour::Behaviour::new(verify, transfer))?
+        .with_swarm_config(|cfg| {
+            cfg.with_idle_connection_timeout(std::time::Duration::from_secs(60))
+        })
+        .build();
+    Ok(swarm)
+}
+
+/// Connect to the server from a fresh identity and run each of the runs on the connection.
+pub async fn run_client(
+    server_address: &str,
+    runs: &[utils::RunParams],
+) -> Result<Vec<utils::RunStats>, Box<dyn std::error::Error>> {
+    let local_key = Keypair::generate_ed25519();
+
+    // The same stack as `with_tcp`, with each step of the connection setup timed.
+    let timeline = setup::Timeline::default();
+    let tcp_config = libp2p::tcp::Config::new().nodelay(true);
+    let mut swarm = libp2p::SwarmBuilder::with_existing_identity(local_key)
+        .with_tokio()
+        .with_other_transport(|key| {
+            let connected = timeline.clone();
+            let noise = setup::Timed::new(
+                libp2p_noise::Config::new(key)?,
+                timeline.clone(),
+                SetupEvent::SecurityNegotiated,
+                Some(SetupEvent::Secured),
+            );
+            let yamux = setup::Timed::new(
+                libp2p_yamux::Config::default(),
+                timeline.clone(),
+                SetupEvent::MuxerNegotiated,
+                None,
+            );
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(
+                libp2p::tcp::tokio::Transport::new(tcp_config)
+                    .map(move |stream, _| {
+                        connected.mark(SetupEvent::Connected);
+                        stream
+                    })
+                    .upgrade(libp2p_core::upgrade::Version::V1Lazy)
+                    .authenticate(noise)
+                    .multiplex(yamux),
+            )
+        })?
+        .with_dns()?
+        .with_behaviour(|_key| client::behaviour::Behaviour::new())?
+        .with_swarm_config(|cfg| {
+            cfg.with_idle_connection_timeout(std::time::Duration::from_secs(60))
+        })
+        .build();
+
+    let addr: libp2p::Multiaddr = server_address.parse()?;
+    timeline.mark(SetupEvent::Dial);
+    swarm.dial(addr)?;
+
+    let server_peer_id = match swarm.next().await.unwrap() {
+        SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+            timeline.mark(SetupEvent::Established);
+            peer_id
+        }
+        SwarmEvent::OutgoingConnectionError { error, .. } => {
+            return Err(utils::PerfError::Dial(error.to_string()).into());
+        }
+        event => {
+            return Err(utils::PerfError::Dial(format!("unexpected event {event:?}")).into());
+        }
+    };
+
+    let mut results = Vec::with_capacity(runs.len());
+    for &params in runs {
+        let usage = utils::UsageMeter::start();
+        for _ in 0..params.parallel {
+            swarm.behaviour_mut().perf(server_peer_id, params)?;
+        }
+
+        let mut substreams = Vec::with_capacity(params.parallel);
+        while substreams.len() < params.parallel {
+            let event = swarm.next().await;
+            tracing::info!("Even: {:?}", event);
+
+            if let Some(SwarmEvent::Behaviour(event)) = event {
+                substreams.push(event.result?);
+            }
+        }
+
+        let mut stats = utils::RunStats::aggregate(substreams);
+        if results.is_empty() {
+            stats.connection_setup = Some(timeline.get().phases());
+        }
+        if params.parallel > 1 {
+            utils::log_parallel(&stats);
+        }
+        let run_usage = usage.finish();
+        utils::log_usage("Run", &run_usage, stats.uploaded + stats.downloaded);
+        stats.usage = Some(run_usage);
+        results.push(stats);
+    }
+
+    Ok(results)
+}
+
+/// Run `runs` over `connections` connections to a server of the same process, listening on
+/// localhost, and return the stats of each run aggregated over the connections.
+///
+/// Only TCP is supported, like the client and server modes.
+pub async fn loopback(
+    layer: TransportLayer,
+    runs: &[RunParams],
+    connections: usize,
+) -> Result<Vec<RunStats>, Box<dyn std::error::Error>> {
+    if layer != TransportLayer::Tcp {
+        return Err(format!("{layer:?} is not supported in loopback").into());
+    }
+    let mut server = server_swarm(Keypair::generate_ed25519(), false, TransferOpts::default())?;
+    server.listen_on("/ip4/127.0.0.1/tcp/0".parse()?)?;
+    let address = loop {
+        if let SwarmEvent::NewListenAddr { address, .. } = server.select_next_some().await {
+            break address;
+        }
+    };
+    let server_address = format!("{address}/p2p/{}", server.local_peer_id());
+    let server = tokio::spawn(async move {
+        loop {
+            server.select_next_some().await;
+        }
+    });
+
+    // Every connection comes from its own swarm, and so from its own identity.
+    let clients = (0..connections).map(|_| run_client(&server_address, runs));
+    let connections = futures::future::join_all(clients).await;
+    server.abort();
+
+    let connections = connections.into_iter().collect::<Result<Vec<_>, _>>()?;
+    Ok(RunStats::aggregate_runs(connections))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use utils::{PerfProtocol, Workload};
+
+    #[test]
+    fn passphrase_peer_id() {
+        let key = NodeKey::from_passphrase("secret").unwrap();
+        assert_eq!(
+            keypair(&key).unwrap().public().to_peer_id().to_string(),
+            "12D3KooWBpZHDZu7YSbvPaPXKhkRNJvR7MkTJMQQAVBKx9mCqz3q"
+        );
+    }
+
+    #[test]
+    fn key_files_are_libp2p_keypairs() {
+        let generated = ed25519::Keypair::generate();
+        let key = NodeKey::from_seed(generated.secret().as_ref().try_into().unwrap());
+        let encoded = key.to_protobuf(&generated.public().to_bytes());
+
+        let generated = Keypair::from(generated);
+        assert_eq!(generated.to_protobuf_encoding().unwrap(), encoded);
+        assert_eq!(
+            Keypair::from_protobuf_encoding(&encoded)
+                .unwrap()
+                .public()
+                .to_peer_id(),
+            keypair(&key).unwrap().public().to_peer_id()
+        );
+    }
+
+    const UPLOAD: u64 = 1024 * 1024 + 3;
+    const DOWNLOAD: u64 = 2 * 1024 * 1024 + 5;
+
+    #[tokio::test]
+    async fn loopback_runs_over_each_connection() {
+        let run = RunParams {
+            workload: Workload::Bytes {
+                upload: UPLOAD,
+                download: DOWNLOAD,
+            },
+            verify: true,
+            transfer: TransferOpts::default(),
+            duplex: false,
+            parallel: 1,
+            protocol: PerfProtocol::Litep2pPerf,
+        };
+        let stats = loopback(TransportLayer::Tcp, &[run, run], 2).await.unwrap();
+
+        assert_eq!(stats.len(), 2);
+        for stats in stats {
+            assert_eq!(stats.uploaded, 2 * UPLOAD);
+            assert_eq!(stats.downloaded, 2 * DOWNLOAD);
+            assert_eq!(stats.connections.len(), 2);
+        }
+    }
+}
@@ -1,100 +1,133 @@
-use clap::Parser as ClapParser;
+use std::time::Duration;
+
 use futures::StreamExt;
 use libp2p_swarm::SwarmEvent;
 
-use utils::Command;
-
-mod client;
-mod server;
+use libp2p_perf::perf;
+use utils::{Command, ServerReady};
 
-mod perf;
+/// How long to wait for the addresses of the other interfaces after the first one.
+const LISTEN_ADDRESSES_WINDOW: Duration = Duration::from_millis(200);
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let command = Command::parse_checked();
+    let output = command.output();
+    utils::set_units(command.units());
+
     tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(move || output.log_writer())
         .init();
-
-    let command = Command::parse();
     match command {
         Command::Server(server_opts) => {
-            let mut bytes = server_opts.node_key.as_bytes().to_vec();
-            if bytes.len() > 32 {
-                bytes.truncate(32);
-            } else if bytes.len() < 32 {
-                bytes.resize(32, 0);
-            }
-
-            let secret_key = libp2p::identity::ed25519::SecretKey::try_from_bytes(bytes)?;
-            let local_key = libp2p::identity::ed25519::Keypair::from(secret_key);
-            let local_key: libp2p::identity::Keypair = local_key.into();
+            let local_key = libp2p_perf::keypair(&server_opts.key.load()?)?;
 
-            let tcp_config = libp2p::tcp::Config::new().nodelay(true);
-            let mut swarm = libp2p::SwarmBuilder::with_existing_identity(local_key)
-                .with_tokio()
-                .with_tcp(
-                    tcp_config,
-                    libp2p_noise::Config::new,
-                    libp2p_yamux::Config::default,
-                )?
-                .with_dns()?
-                .with_behaviour(|_key| crate::server::behaviour::Behaviour::new())?
-                .with_swarm_config(|cfg| {
-                    cfg.with_idle_connection_timeout(std::time::Duration::from_secs(60))
-                })
-                .build();
+            let mut swarm =
+                libp2p_perf::server_swarm(local_key, server_opts.verify, server_opts.transfer)?;
 
             swarm.listen_on(server_opts.listen_address.parse()?)?;
 
+            // Announce the addresses once listening started, with the addresses of every
+            // interface of a wildcard address, which are reported shortly after each other.
+            let mut listen_addresses = Vec::new();
+            loop {
+                let event = if listen_addresses.is_empty() {
+                    swarm.next().await
+                } else {
+                    match tokio::time::timeout(LISTEN_ADDRESSES_WINDOW, swarm.next()).await {
+                        Ok(event) => event,
+                        Err(_) => break,
+                    }
+                };
+                tracing::info!("Event: {:?}", event);
+                match event {
+                    Some(SwarmEvent::NewListenAddr { address, .. }) => {
+                        listen_addresses.push(address)
+                    }
+                    Some(SwarmEvent::ListenerError { error, .. }) => return Err(error.into()),
+                    Some(SwarmEvent::ListenerClosed { reason, .. }) => {
+                        return Err(match reason {
+                            Ok(()) => "listener closed before listening".into(),
+                            Err(error) => error.into(),
+                        });
+                    }
+                    None => return Err("swarm stopped before listening".into()),
+                    _ => {}
+                }
+            }
+            ServerReady::new(
+                perf::IMPLEMENTATION,
+                swarm.local_peer_id(),
+                listen_addresses,
+            )
+            .announce(server_opts.ready_file.as_deref())?;
+
             loop {
                 let event = swarm.next().await;
                 tracing::info!("Event: {:?}", event);
             }
         }
         Command::Client(client_opts) => {
-            let local_key = libp2p::identity::Keypair::generate_ed25519();
-
-            let tcp_config = libp2p::tcp::Config::new().nodelay(true);
-            let mut swarm = libp2p::SwarmBuilder::with_existing_identity(local_key)
-                .with_tokio()
-                .with_tcp(
-                    tcp_config,
-                    libp2p_noise::Config::new,
-                    libp2p_yamux::Config::default,
-                )?
-                .with_dns()?
-                .with_behaviour(|_key| crate::client::behaviour::Behaviour::new())?
-                .with_swarm_config(|cfg| {
-                    cfg.with_idle_connection_timeout(std::time::Duration::from_secs(60))
-                })
-                .build();
-
-            let addr: libp2p::Multiaddr = client_opts.server_address.parse()?;
-            swarm.dial(addr)?;
-
-            let server_peer_id = match swarm.next().await.unwrap() {
-                SwarmEvent::ConnectionEstablished { peer_id, .. } => peer_id,
-                e => panic!("{e:?}"),
-            };
+            let runs = client_opts.schedule();
+            // Every connection comes from its own swarm, and so from its own identity.
+            let clients = (0..client_opts.connections)
+                .map(|_| libp2p_perf::run_client(&client_opts.server_address, &runs));
 
-            swarm.behaviour_mut().perf(
-                server_peer_id,
-                client_opts.upload_bytes as u64,
-                client_opts.download_bytes as u64,
-            )?;
+            let mut connections = Vec::with_capacity(client_opts.connections);
+            for result in futures::future::join_all(clients).await {
+                match result {
+                    Ok(connection) => connections.push(connection),
+                    Err(error) => {
+                        if output == utils::OutputFormat::Json {
+                            client_opts.failed(perf::IMPLEMENTATION, &error).print();
+                        }
+                        return Err(error);
+                    }
+                }
+            }
 
-            loop {
-                let event = swarm.next().await;
-                tracing::info!("Even: {:?}", event);
+            // Each connection ran the same runs, aggregate them run by run.
+            client_opts.report(
+                perf::IMPLEMENTATION,
+                utils::RunStats::aggregate_runs(connections),
+            );
 
-                match event {
-                    Some(SwarmEvent::Behaviour(..)) => {
-                        return Ok(());
+            Ok(())
+        }
+        Command::Loopback(client_opts) => {
+            let result = libp2p_perf::loopback(
+                client_opts.transport_layer,
+                &client_opts.schedule(),
+                client_opts.connections,
+            )
+            .await;
+            match result {
+                Ok(stats) => client_opts.report(perf::IMPLEMENTATION, stats),
+                Err(error) => {
+                    if output == utils::OutputFormat::Json {
+                        client_opts.failed(perf::IMPLEMENTATION, &error).print();
                     }
-                    _ => {}
+                    return Err(error);
                 }
             }
+            Ok(())
+        }
+        Command::PeerId(key_opts) => {
+            let local_key = libp2p_perf::keypair(&key_opts.load()?)?;
+            println!("{}", local_key.public().to_peer_id());
+            Ok(())
+        }
+        Command::GenerateKey(opts) => {
+            let keypair = libp2p::identity::ed25519::Keypair::generate();
+            utils::NodeKey::from_seed(keypair.secret().as_ref().try_into()?)
+                .write(&opts.key_file, &keypair.public().to_bytes())?;
+            println!(
+                "{}",
+                libp2p::identity::PublicKey::from(keypair.public()).to_peer_id()
+            );
+            Ok(())
         }
         _ => panic!("Command unimplemented"),
-    };
+    }
 }
@@ -1,100 +1,246 @@
-use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use futures::future;
+use libp2p_core::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use libp2p_swarm::{Stream, StreamProtocol};
+use utils::{PerfProtocol, ProtocolVersion};
+use void::Void;
 
-pub const PROTOCOL_NAME: &str = "/litep2p-perf/1.0.0";
-const LOG_TARGET: &str = "litep2p-perf";
+pub const PROTOCOL_NAME: &str = "/litep2p-perf/2.0.0";
+pub const PROTOCOL_NAME_V1: &str = "/litep2p-perf/1.0.0";
+pub const PROTOCOL_NAME_PERF: &str = "/perf/1.0.0";
+pub(crate) const LOG_TARGET: &str = "litep2p-perf";
 
-async fn read_u64<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
-    substream: &mut S,
-) -> Result<u64, std::io::Error> {
-    let mut buf = [0u8; 8];
-    substream.read_exact(&mut buf).await?;
-    Ok(u64::from_be_bytes(buf))
+/// Name of this implementation in the reports exchanged with the remote.
+pub const IMPLEMENTATION: &str = "libp2p";
+
+/// Upgrade negotiating one of the perf protocols, in order of preference.
+#[derive(Debug, Clone)]
+pub struct Upgrade {
+    protocols: Vec<StreamProtocol>,
 }
 
-async fn write_u64<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
-    substream: &mut S,
-    value: u64,
-) -> Result<(), std::io::Error> {
-    substream.write_all(&value.to_be_bytes()).await?;
-    Ok(())
+impl UpgradeInfo for Upgrade {
+    type Info = StreamProtocol;
+    type InfoIter = Vec<StreamProtocol>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.protocols.clone()
+    }
 }
 
-async fn recv_bytes<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
-    substream: &mut S,
-    to_recv: u64,
-) -> Result<(), std::io::Error> {
-    let mut buf = vec![0u8; 1024];
-    let mut total = 0;
-    while total < to_recv {
-        let n = substream.read(&mut buf).await?;
-        if n == 0 {
-            break;
-        }
-        total += n as u64;
+impl InboundUpgrade<Stream> for Upgrade {
+    type Output = (Stream, ProtocolVersion);
+    type Error = Void;
+    type Future = future::Ready<Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, stream: Stream, protocol: Self::Info) -> Self::Future {
+        future::ready(Ok((stream, version(&protocol))))
     }
-    Ok(())
 }
 
-async fn send_bytes<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
-    substream: &mut S,
-    to_send: u64,
-) -> Result<(), std::io::Error> {
-    let buf = vec![0u8; 1024];
-    let mut total = 0;
-    while total < to_send {
-        substream.write_all(&buf).await?;
-        total += buf.len() as u64;
+impl OutboundUpgrade<Stream> for Upgrade {
+    type Output = (Stream, ProtocolVersion);
+    type Error = Void;
+    type Future = future::Ready<Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, stream: Stream, protocol: Self::Info) -> Self::Future {
+        future::ready(Ok((stream, version(&protocol))))
     }
-    Ok(())
 }
 
-pub async fn server_mode<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
-    mut substream: S,
-) -> Result<(), std::io::Error> {
-    // Step 1. Read the download bytes.
-    let to_recv = read_u64(&mut substream).await?;
-    // Step 2. Receive the download bytes.
-    recv_bytes(&mut substream, to_recv).await?;
+fn version(protocol: &StreamProtocol) -> ProtocolVersion {
+    match protocol.as_ref() {
+        PROTOCOL_NAME => ProtocolVersion::V2,
+        PROTOCOL_NAME_V1 => ProtocolVersion::V1,
+        _ => ProtocolVersion::Perf,
+    }
+}
 
-    // Step 3. Read the upload bytes.
-    let to_send = read_u64(&mut substream).await?;
-    // Step 4. Send the upload bytes.
-    send_bytes(&mut substream, to_send).await?;
+/// Upgrade accepting every perf protocol.
+pub fn upgrade() -> Upgrade {
+    Upgrade {
+        protocols: vec![
+            StreamProtocol::new(PROTOCOL_NAME),
+            StreamProtocol::new(PROTOCOL_NAME_V1),
+            StreamProtocol::new(PROTOCOL_NAME_PERF),
+        ],
+    }
+}
 
-    Ok(())
+/// Upgrade requesting `protocol`, preferring v2 over v1 for `/litep2p-perf`.
+pub fn client_upgrade(protocol: PerfProtocol) -> Upgrade {
+    let protocols = match protocol {
+        PerfProtocol::Litep2pPerf => vec![
+            StreamProtocol::new(PROTOCOL_NAME),
+            StreamProtocol::new(PROTOCOL_NAME_V1),
+        ],
+        PerfProtocol::Perf => vec![StreamProtocol::new(PROTOCOL_NAME_PERF)],
+    };
+    Upgrade { protocols }
 }
 
-pub async fn client_mode<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
-    mut substream: S,
-    upload_bytes: u64,
-    download_bytes: u64,
-) -> Result<(), std::io::Error> {
-    // Step 1. Send the upload bytes.
-    write_u64(&mut substream, upload_bytes).await?;
-    // Step 2. Send the upload bytes.
-    let now = std::time::Instant::now();
-    send_bytes(&mut substream, upload_bytes).await?;
-    let elapsed = now.elapsed();
-    tracing::info!(
-        target: LOG_TARGET,
-        "Uploaded {} bytes in {:.4}s bandwidth {}",
-        utils::format_bytes(upload_bytes as usize),
-        elapsed.as_secs_f64(),
-        utils::format_bandwidth(elapsed, upload_bytes as usize)
-    );
-    // Step 3. Send the download bytes.
-    write_u64(&mut substream, download_bytes).await?;
-    // Step 4. Receive the download bytes.
-    let now = std::time::Instant::now();
-    recv_bytes(&mut substream, download_bytes).await?;
-    let elapsed = now.elapsed();
-    tracing::info!(
-        target: LOG_TARGET,
-        "Downloaded {} bytes in {:.4}s bandwidth {}",
-        utils::format_bytes(download_bytes as usize),
-        elapsed.as_secs_f64(),
-        utils::format_bandwidth(elapsed, download_bytes as usize)
-    );
-
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use libp2p::{Swarm, swarm::SwarmEvent};
+    use utils::{RunParams, TransferOpts, Workload};
+
+    const UPLOAD: u64 = 1024 * 1024 + 3;
+    const DOWNLOAD: u64 = 2 * 1024 * 1024 + 5;
+
+    fn swarm<B: libp2p::swarm::NetworkBehaviour>(behaviour: B) -> Swarm<B> {
+        libp2p::SwarmBuilder::with_new_identity()
+            .with_tokio()
+            .with_tcp(
+                libp2p::tcp::Config::default(),
+                libp2p_noise::Config::new,
+                libp2p_yamux::Config::default,
+            )
+            .unwrap()
+            .with_behaviour(|_| behaviour)
+            .unwrap()
+            .with_swarm_config(|config| {
+                config.with_idle_connection_timeout(Duration::from_secs(60))
+            })
+            .build()
+    }
+
+    fn upstream_swarm<B: upstream_libp2p::swarm::NetworkBehaviour>(
+        behaviour: B,
+    ) -> upstream_libp2p::Swarm<B> {
+        upstream_libp2p::SwarmBuilder::with_new_identity()
+            .with_tokio()
+            .with_tcp(
+                upstream_libp2p::tcp::Config::default(),
+                upstream_libp2p::noise::Config::new,
+                upstream_libp2p::yamux::Config::default,
+            )
+            .unwrap()
+            .with_behaviour(|_| behaviour)
+            .unwrap()
+            .with_swarm_config(|config| {
+                config.with_idle_connection_timeout(Duration::from_secs(60))
+            })
+            .build()
+    }
+
+    #[tokio::test]
+    async fn client_against_upstream_server() {
+        let mut server = upstream_swarm(upstream_perf::server::Behaviour::new());
+        server
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+        let address = loop {
+            if let upstream_libp2p::swarm::SwarmEvent::NewListenAddr { address, .. } =
+                server.select_next_some().await
+            {
+                break address;
+            }
+        };
+        let server_peer_id = server.local_peer_id().to_string().parse().unwrap();
+        let (server_tx, mut server_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                if let upstream_libp2p::swarm::SwarmEvent::Behaviour(event) =
+                    server.select_next_some().await
+                {
+                    let _ = server_tx.send(event.stats);
+                }
+            }
+        });
+
+        let mut client = swarm(crate::client::behaviour::Behaviour::new());
+        client
+            .dial(address.to_string().parse::<libp2p::Multiaddr>().unwrap())
+            .unwrap();
+        let run = RunParams {
+            workload: Workload::Bytes {
+                upload: UPLOAD,
+                download: DOWNLOAD,
+            },
+            verify: false,
+            transfer: TransferOpts::default(),
+            duplex: false,
+            parallel: 1,
+            protocol: PerfProtocol::Perf,
+        };
+        let stats = loop {
+            match client.select_next_some().await {
+                SwarmEvent::ConnectionEstablished { .. } => {
+                    client.behaviour_mut().perf(server_peer_id, run).unwrap();
+                }
+                SwarmEvent::Behaviour(event) => break event.result.unwrap(),
+                _ => {}
+            }
+        };
+
+        assert_eq!(stats.uploaded, UPLOAD);
+        assert_eq!(stats.downloaded, DOWNLOAD);
+        let server_run = server_rx.recv().await.unwrap();
+        assert_eq!(server_run.params.to_receive as u64, UPLOAD);
+        assert_eq!(server_run.params.to_send as u64, DOWNLOAD);
+    }
+
+    #[tokio::test]
+    async fn server_against_upstream_client() {
+        let mut server = swarm(crate::server::behaviour::Behaviour::new(
+            false,
+            TransferOpts::default(),
+        ));
+        server
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+        let address = loop {
+            if let SwarmEvent::NewListenAddr { address, .. } = server.select_next_some().await {
+                break address;
+            }
+        };
+        let server_peer_id = server.local_peer_id().to_string().parse().unwrap();
+        tokio::spawn(async move {
+            loop {
+                server.select_next_some().await;
+            }
+        });
+
+        let mut client = upstream_swarm(upstream_perf::client::Behaviour::new());
+        client
+            .dial(
+                address
+                    .to_string()
+                    .parse::<upstream_libp2p::Multiaddr>()
+                    .unwrap(),
+            )
+            .unwrap();
+        let duration = loop {
+            match client.select_next_some().await {
+                upstream_libp2p::swarm::SwarmEvent::ConnectionEstablished { .. } => {
+                    client
+                        .behaviour_mut()
+                        .perf(
+                            server_peer_id,
+                            upstream_perf::RunParams {
+                                to_send: UPLOAD as usize,
+                                to_receive: DOWNLOAD as usize,
+                            },
+                        )
+                        .unwrap();
+                }
+                upstream_libp2p::swarm::SwarmEvent::Behaviour(upstream_perf::client::Event {
+                    result,
+                    ..
+                }) => {
+                    if let upstream_perf::RunUpdate::Final(run) = result.unwrap() {
+                        break run.duration;
+                    }
+                }
+                _ => {}
+            }
+        };
+
+        assert!(!duration.upload.is_zero());
+        assert!(!duration.download.is_zero());
+    }
 }
@@ -9,20 +9,29 @@ use libp2p_swarm::{
     ConnectionId, FromSwarm, NetworkBehaviour, THandlerInEvent, THandlerOutEvent, ToSwarm,
 };
 
+use utils::TransferOpts;
+
 use crate::server::handler::Handler;
 
 #[derive(Debug)]
 pub struct Event {}
 
-#[derive(Default)]
 pub struct Behaviour {
     /// Queue of actions to return when polled.
     queued_events: VecDeque<ToSwarm<Event, THandlerInEvent<Self>>>,
+    /// Verify the uploaded payload.
+    verify: bool,
+    /// How the payload is written and read.
+    transfer: TransferOpts,
 }
 
 impl Behaviour {
-    pub fn new() -> Self {
-        Self::default()
+    pub fn new(verify: bool, transfer: TransferOpts) -> Self {
+        Self {
+            queued_events: VecDeque::new(),
+            verify,
+            transfer,
+        }
     }
 }
 
@@ -37,7 +46,7 @@ impl NetworkBehaviour for Behaviour {
         _local_addr: &libp2p_core::Multiaddr,
         _remote_addr: &libp2p_core::Multiaddr,
     ) -> Result<libp2p_swarm::THandler<Self>, libp2p_swarm::ConnectionDenied> {
-        Ok(Handler::default())
+        Ok(Handler::new(self.verify, self.transfer))
     }
 
     fn handle_established_outbound_connection(
@@ -48,7 +57,7 @@ impl NetworkBehaviour for Behaviour {
         _role_override: libp2p_core::Endpoint,
         _port_use: PortUse,
     ) -> Result<libp2p_swarm::THandler<Self>, libp2p_swarm::ConnectionDenied> {
-        Ok(Handler::default())
+        Ok(Handler::new(self.verify, self.transfer))
     }
 
     fn on_swarm_event(&mut self, _event: FromSwarm) {}
@@ -1,50 +1,48 @@
 use std::task::{Context, Poll};
 
 use futures::{StreamExt, future::BoxFuture, stream::FuturesUnordered};
-use libp2p_core::upgrade::{DeniedUpgrade, ReadyUpgrade};
+use libp2p_core::upgrade::DeniedUpgrade;
 use libp2p_swarm::{
-    ConnectionHandler, ConnectionHandlerEvent, StreamProtocol, SubstreamProtocol,
+    ConnectionHandler, ConnectionHandlerEvent, SubstreamProtocol,
     handler::{
         ConnectionEvent, DialUpgradeError, FullyNegotiatedInbound, FullyNegotiatedOutbound,
         ListenUpgradeError,
     },
 };
+use utils::{PerfError, TransferOpts};
 use void::Void;
 
 #[derive(Debug)]
 pub struct Event {}
 
 pub struct Handler {
-    inbound: FuturesUnordered<BoxFuture<'static, Result<(), std::io::Error>>>,
+    inbound: FuturesUnordered<BoxFuture<'static, Result<(), PerfError>>>,
+    /// Verify the uploaded payload.
+    verify: bool,
+    /// How the payload is written and read.
+    transfer: TransferOpts,
 }
 
 impl Handler {
-    pub fn new() -> Self {
+    pub fn new(verify: bool, transfer: TransferOpts) -> Self {
         Self {
             inbound: FuturesUnordered::new(),
+            verify,
+            transfer,
         }
     }
 }
 
-impl Default for Handler {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
 impl ConnectionHandler for Handler {
     type FromBehaviour = Void;
     type ToBehaviour = Event;
-    type InboundProtocol = ReadyUpgrade<StreamProtocol>;
+    type InboundProtocol = crate::perf::Upgrade;
     type OutboundProtocol = DeniedUpgrade;
     type OutboundOpenInfo = Void;
     type InboundOpenInfo = ();
 
     fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
-        SubstreamProtocol::new(
-            ReadyUpgrade::new(StreamProtocol::new(crate::perf::PROTOCOL_NAME)),
-            (),
-        )
+        SubstreamProtocol::new(crate::perf::upgrade(), ())
     }
 
     fn on_behaviour_event(&mut self, v: Self::FromBehaviour) {
@@ -65,7 +63,18 @@ impl ConnectionHandler for Handler {
                 protocol,
                 info: _,
             }) => {
-                let future = Box::pin(async move { crate::perf::server_mode(protocol).await });
+                let (stream, version) = protocol;
+                let (verify, transfer) = (self.verify, self.transfer);
+                let future = Box::pin(async move {
+                    utils::server_mode(
+                        stream,
+                        version,
+                        verify,
+                        transfer,
+                        crate::perf::IMPLEMENTATION,
+                    )
+                    .await
+                });
                 self.inbound.push(future);
             }
             // TODO: remove when Rust 1.82 is MSRV
@@ -98,7 +107,10 @@ impl ConnectionHandler for Handler {
     ) -> Poll<
         ConnectionHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::ToBehaviour>,
     > {
-        if let Poll::Ready(Some(_res)) = self.inbound.poll_next_unpin(cx) {
+        if let Poll::Ready(Some(result)) = self.inbound.poll_next_unpin(cx) {
+            if let Err(error) = result {
+                tracing::error!(target: crate::perf::LOG_TARGET, "server mode error: {:?}", error);
+            }
             return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(Event {}));
         }
 
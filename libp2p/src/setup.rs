@@ -0,0 +1,95 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use libp2p_core::{
+    UpgradeInfo,
+    upgrade::{InboundConnectionUpgrade, OutboundConnectionUpgrade},
+};
+use utils::{SetupEvent, SetupTimeline};
+
+/// Setup of the connection of a client swarm, shared with its transport.
+#[derive(Debug, Clone, Default)]
+pub struct Timeline(Arc<Mutex<SetupTimeline>>);
+
+impl Timeline {
+    pub fn mark(&self, event: SetupEvent) {
+        self.0
+            .lock()
+            .expect("timeline lock")
+            .mark(event, Instant::now());
+    }
+
+    pub fn get(&self) -> SetupTimeline {
+        *self.0.lock().expect("timeline lock")
+    }
+}
+
+/// Connection upgrade recording when the dialer negotiated it and when it completed.
+#[derive(Debug, Clone)]
+pub struct Timed<U> {
+    inner: U,
+    timeline: Timeline,
+    negotiated: SetupEvent,
+    completed: Option<SetupEvent>,
+}
+
+impl<U> Timed<U> {
+    pub fn new(
+        inner: U,
+        timeline: Timeline,
+        negotiated: SetupEvent,
+        completed: Option<SetupEvent>,
+    ) -> Self {
+        Self {
+            inner,
+            timeline,
+            negotiated,
+            completed,
+        }
+    }
+}
+
+impl<U: UpgradeInfo> UpgradeInfo for Timed<U> {
+    type Info = U::Info;
+    type InfoIter = U::InfoIter;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.inner.protocol_info()
+    }
+}
+
+impl<C, U: InboundConnectionUpgrade<C>> InboundConnectionUpgrade<C> for Timed<U> {
+    type Output = U::Output;
+    type Error = U::Error;
+    type Future = U::Future;
+
+    fn upgrade_inbound(self, socket: C, info: Self::Info) -> Self::Future {
+        self.inner.upgrade_inbound(socket, info)
+    }
+}
+
+impl<C, U> OutboundConnectionUpgrade<C> for Timed<U>
+where
+    U: OutboundConnectionUpgrade<C>,
+    U::Future: Send + 'static,
+{
+    type Output = U::Output;
+    type Error = U::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<U::Output, U::Error>> + Send>>;
+
+    fn upgrade_outbound(self, socket: C, info: Self::Info) -> Self::Future {
+        self.timeline.mark(self.negotiated);
+        let upgrade = self.inner.upgrade_outbound(socket, info);
+        Box::pin(async move {
+            let result = upgrade.await;
+            if let (Ok(_), Some(completed)) = (&result, self.completed) {
+                self.timeline.mark(completed);
+            }
+            result
+        })
+    }
+}
@@ -6,13 +6,21 @@ for index in 1 32 64 128 256; do
     echo "Running test with $index substreams..."
 
     # Start the server
-    RUST_LOG=info cargo run -- server --listen-address "/ip6/::/tcp/33333" --node-key "secret" > /dev/null 2>&1 &
+    READY_FILE=$(mktemp -u)
+    RUST_LOG=info cargo run -- server --listen-address "/ip6/::1/tcp/33333" --node-key "secret" --ready-file "$READY_FILE" > /dev/null 2>&1 &
 
     # Get the PID of the server
     SERVER_PID=$!
 
+    # Wait for the server to announce its address.
+    until [ -f "$READY_FILE" ]; do
+        sleep 0.1
+    done
+    SERVER_ADDRESS=$(sed -n 's/.*"listen_addresses":\["\([^"]*\)".*/\1/p' "$READY_FILE")
+    rm "$READY_FILE"
+
     # Start the client and capture the output
-    OUTPUT=$(RUST_LOG=info cargo run -- client-substream --server-address "/ip6/::1/tcp/33333/p2p/12D3KooWBpZHDZu7YSbvPaPXKhkRNJvR7MkTJMQQAVBKx9mCqz3q" --substreams $index | grep "Average time to open substreams")
+    OUTPUT=$(RUST_LOG=info cargo run -- client-substream --server-address "$SERVER_ADDRESS" --substreams $index | grep "Average time to open substreams")
 
     # Store the result in an associative array
     results[$index]="$OUTPUT"
@@ -0,0 +1,243 @@
+//! litep2p nodes running the perf protocols, as servers, clients or both in one process.
+
+use std::time::Instant;
+
+use litep2p::{
+    crypto::ed25519::{Keypair, SecretKey},
+    Litep2p, Litep2pEvent,
+};
+use utils::{
+    NodeKey, PerfError, RunParams, RunStats, SetupEvent, SetupTimeline, TransferOpts,
+    TransportLayer,
+};
+
+pub mod perf;
+
+/// The ed25519 keypair of `key`, the same libp2p derives.
+pub fn keypair(key: &NodeKey) -> Result<Keypair, Box<dyn std::error::Error>> {
+    let keypair = Keypair::from(SecretKey::try_from_bytes(key.seed())?);
+    key.verify_public(&keypair.public().to_bytes())?;
+    Ok(keypair)
+}
+
+/// Build a node serving the perf protocols on `listen_address`.
+pub fn server_node(
+    keypair: Keypair,
+    layer: TransportLayer,
+    listen_address: litep2p::types::multiaddr::Multiaddr,
+    verify: bool,
+    transfer: TransferOpts,
+) -> Result<Litep2p, Box<dyn std::error::Error>> {
+    let (perf, aliases, _handle) = perf::Perf::new(perf::PerfMode::Server { verify, transfer });
+
+    let mut litep2p_config = litep2p::config::ConfigBuilder::new()
+        .with_keypair(keypair)
+        .with_user_protocol(Box::new(perf));
+    for alias in aliases {
+        litep2p_config = litep2p_config.with_user_protocol(Box::new(alias));
+    }
+
+    match layer {
+        TransportLayer::Tcp => {
+            litep2p_config = litep2p_config.with_tcp(litep2p::transport::tcp::config::Config {
+                listen_addresses: vec![listen_address],
+                reuse_port: true,
+                nodelay: true,
+                ..Default::default()
+            });
+        }
+        TransportLayer::WebSocket => {
+            litep2p_config =
+                litep2p_config.with_websocket(litep2p::transport::websocket::config::Config {
+                    listen_addresses: vec![listen_address],
+                    ..Default::default()
+                });
+            tracing::info!("Using WebSocket transport layer");
+        }
+        TransportLayer::WebRTC => {
+            litep2p_config =
+                litep2p_config.with_webrtc(litep2p::transport::webrtc::config::Config {
+                    listen_addresses: vec![listen_address],
+                    ..Default::default()
+                });
+            tracing::info!("Using WebRTC transport layer");
+        }
+    };
+
+    Ok(Litep2p::new(litep2p_config.build())?)
+}
+
+/// Build a client node running `mode`, with a fresh identity.
+pub fn client_node(
+    layer: TransportLayer,
+    mode: perf::PerfMode,
+) -> Result<(Litep2p, tokio::sync::oneshot::Receiver<perf::ClientResult>), Box<dyn std::error::Error>>
+{
+    let (perf_client, aliases, perf_handle) = perf::Perf::new(mode);
+    let mut litep2p_config = litep2p::config::ConfigBuilder::new();
+
+    match layer {
+        TransportLayer::Tcp => {
+            litep2p_config = litep2p_config.with_tcp(litep2p::transport::tcp::config::Config {
+                reuse_port: true,
+                nodelay: true,
+                ..Default::default()
+            });
+        }
+        TransportLayer::WebSocket => {
+            litep2p_config =
+                litep2p_config.with_websocket(litep2p::transport::websocket::config::Config {
+                    ..Default::default()
+                });
+        }
+        TransportLayer::WebRTC => {
+            litep2p_config =
+                litep2p_config.with_webrtc(litep2p::transport::webrtc::config::Config {
+                    ..Default::default()
+                });
+        }
+    }
+
+    litep2p_config = litep2p_config.with_user_protocol(Box::new(perf_client));
+    for alias in aliases {
+        litep2p_config = litep2p_config.with_user_protocol(Box::new(alias));
+    }
+
+    Ok((Litep2p::new(litep2p_config.build())?, perf_handle))
+}
+
+/// Drive a client node until its perf protocol completed all runs.
+///
+/// The setup of the connection dialed at `dial` is reported with the first run, from the dial
+/// until litep2p reported the connection. litep2p does not expose the steps in between, so the
+/// setup is marked as total only.
+pub async fn run_client(
+    mut litep2p: Litep2p,
+    mut perf_handle: tokio::sync::oneshot::Receiver<perf::ClientResult>,
+    dial: Instant,
+) -> Result<Vec<RunStats>, PerfError> {
+    let mut established = None;
+    loop {
+        tokio::select! {
+            event = litep2p.next_event() => match event {
+                Some(event @ Litep2pEvent::ConnectionEstablished { .. }) => {
+                    established.get_or_insert_with(Instant::now);
+                    tracing::info!("Event: {event:?}");
+                }
+                Some(Litep2pEvent::DialFailure { address, error }) => {
+                    return Err(PerfError::Dial(format!("{address}: {error}")));
+                }
+                Some(Litep2pEvent::ListDialFailures { errors }) => {
+                    return Err(PerfError::Dial(format!("{errors:?}")));
+                }
+                Some(event) => tracing::info!("Event: {event:?}"),
+                None => {}
+            },
+            result = &mut perf_handle => {
+                // The perf protocol always reports, unless its task panicked or was cancelled.
+                let mut results = result.map_err(|error| PerfError::Task(error.to_string()))??;
+                let mut timeline = SetupTimeline::new();
+                timeline.mark(SetupEvent::Dial, dial);
+                if let Some(established) = established {
+                    timeline.mark(SetupEvent::Established, established);
+                }
+                if let Some(first) = results.first_mut() {
+                    first.connection_setup = Some(timeline.phases());
+                }
+                return Ok(results);
+            }
+        }
+    }
+}
+
+/// Run `runs` over `connections` connections to a server of the same process, listening on
+/// localhost, and return the stats of each run aggregated over the connections.
+///
+/// WebRTC is not supported, litep2p only accepts WebRTC connections.
+pub async fn loopback(
+    layer: TransportLayer,
+    runs: &[RunParams],
+    connections: usize,
+) -> Result<Vec<RunStats>, Box<dyn std::error::Error>> {
+    let listen_address = match layer {
+        TransportLayer::Tcp => "/ip4/127.0.0.1/tcp/0",
+        TransportLayer::WebSocket => "/ip4/127.0.0.1/tcp/0/ws",
+        TransportLayer::WebRTC => return Err("WebRTC is not supported in loopback".into()),
+    };
+    let mut server = server_node(
+        Keypair::generate(),
+        layer,
+        listen_address.parse()?,
+        false,
+        TransferOpts::default(),
+    )?;
+    let server_address = format!(
+        "{}/p2p/{}",
+        server
+            .listen_addresses()
+            .next()
+            .ok_or("the server is not listening")?,
+        server.local_peer_id()
+    );
+    let server = tokio::spawn(async move { while server.next_event().await.is_some() {} });
+
+    let mut clients = Vec::with_capacity(connections);
+    for _ in 0..connections {
+        let (mut client, perf_handle) = client_node(
+            layer,
+            perf::PerfMode::Client {
+                runs: runs.to_vec(),
+            },
+        )?;
+        let dial = Instant::now();
+        client.dial_address(server_address.parse()?).await?;
+        clients.push(run_client(client, perf_handle, dial));
+    }
+    let connections = futures::future::join_all(clients).await;
+    server.abort();
+
+    let connections = connections.into_iter().collect::<Result<Vec<_>, _>>()?;
+    Ok(RunStats::aggregate_runs(connections))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use utils::{PerfProtocol, Workload};
+
+    #[test]
+    fn passphrase_peer_id() {
+        let key = NodeKey::from_passphrase("secret").unwrap();
+        assert_eq!(
+            keypair(&key).unwrap().public().to_peer_id().to_string(),
+            "12D3KooWBpZHDZu7YSbvPaPXKhkRNJvR7MkTJMQQAVBKx9mCqz3q"
+        );
+    }
+
+    const UPLOAD: u64 = 1024 * 1024 + 3;
+    const DOWNLOAD: u64 = 2 * 1024 * 1024 + 5;
+
+    #[tokio::test]
+    async fn loopback_runs_over_each_connection() {
+        let run = RunParams {
+            workload: Workload::Bytes {
+                upload: UPLOAD,
+                download: DOWNLOAD,
+            },
+            verify: true,
+            transfer: TransferOpts::default(),
+            duplex: false,
+            parallel: 1,
+            protocol: PerfProtocol::Litep2pPerf,
+        };
+        let stats = loopback(TransportLayer::Tcp, &[run, run], 2).await.unwrap();
+
+        assert_eq!(stats.len(), 2);
+        for stats in stats {
+            assert_eq!(stats.uploaded, 2 * UPLOAD);
+            assert_eq!(stats.downloaded, 2 * DOWNLOAD);
+            assert_eq!(stats.connections.len(), 2);
+        }
+    }
+}
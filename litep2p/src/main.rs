@@ -1,75 +1,40 @@
-use clap::Parser as ClapParser;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
 
-use utils::Command;
-
-mod perf;
+use litep2p_perf::perf;
+use utils::{Command, ServerReady};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+    let command = Command::parse_checked();
+    let output = command.output();
+    utils::set_units(command.units());
+
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(move || output.log_writer())
+                .with_filter(tracing_subscriber::EnvFilter::from_default_env()),
+        )
         .init();
 
-    let command = Command::parse();
-
-    let (perf_client, mut perf_handle, server_address, layer) = match command {
+    let mut client = None;
+    let (nodes, server_address, layer): (Vec<_>, _, _) = match command {
         Command::Server(server_opts) => {
-            let (perf, _handle) = perf::Perf::new(perf::PerfMode::Server);
-
-            let mut bytes = server_opts.node_key.as_bytes().to_vec();
-            if bytes.len() > 32 {
-                bytes.truncate(32);
-            } else if bytes.len() < 32 {
-                bytes.resize(32, 0);
-            }
-
-            let secret_key = litep2p::crypto::ed25519::SecretKey::try_from_bytes(&mut bytes)?;
-            let mut litep2p_config = litep2p::config::ConfigBuilder::new()
-                .with_keypair(secret_key.into())
-                .with_user_protocol(Box::new(perf));
-
-            match server_opts.transport_layer {
-                utils::TransportLayer::Tcp => {
-                    litep2p_config =
-                        litep2p_config.with_tcp(litep2p::transport::tcp::config::Config {
-                            listen_addresses: vec![server_opts
-                                .listen_address
-                                .parse()
-                                .expect("Valid listen address")],
-                            reuse_port: true,
-                            nodelay: true,
-                            ..Default::default()
-                        });
-                }
-                utils::TransportLayer::WebSocket => {
-                    litep2p_config = litep2p_config.with_websocket(
-                        litep2p::transport::websocket::config::Config {
-                            listen_addresses: vec![server_opts
-                                .listen_address
-                                .parse()
-                                .expect("Valid listen address")],
-                            ..Default::default()
-                        },
-                    );
-                    tracing::info!("Using WebSocket transport layer");
-                }
-                utils::TransportLayer::WebRTC => {
-                    litep2p_config =
-                        litep2p_config.with_webrtc(litep2p::transport::webrtc::config::Config {
-                            listen_addresses: vec![server_opts
-                                .listen_address
-                                .parse()
-                                .expect("Valid listen address")],
-                            ..Default::default()
-                        });
-                    tracing::info!("Using WebRTC transport layer");
-                }
-            };
-
-            let mut litep2p = litep2p::Litep2p::new(litep2p_config.build())?;
+            let mut litep2p = litep2p_perf::server_node(
+                litep2p_perf::keypair(&server_opts.key.load()?)?,
+                server_opts.transport_layer,
+                server_opts
+                    .listen_address
+                    .parse()
+                    .expect("Valid listen address"),
+                server_opts.verify,
+                server_opts.transfer,
+            )?;
 
             let listen_addr: Vec<_> = litep2p.listen_addresses().collect();
             tracing::info!("Server listening on address: {listen_addr:?}");
+            ServerReady::new(perf::IMPLEMENTATION, litep2p.local_peer_id(), listen_addr)
+                .announce(server_opts.ready_file.as_deref())?;
 
             while let Some(event) = litep2p.next_event().await {
                 tracing::info!("Event: {event:?}");
@@ -79,76 +44,87 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
 
         Command::Client(client_opts) => {
-            let (perf, handle) = perf::Perf::new(perf::PerfMode::Client {
-                upload_bytes: client_opts.upload_bytes as u64,
-                download_bytes: client_opts.download_bytes as u64,
-            });
-
-            (
-                perf,
-                handle,
-                client_opts.server_address,
-                client_opts.transport_layer,
-            )
+            let runs = client_opts.schedule();
+            // Every connection comes from its own node, and so from its own identity.
+            let nodes = (0..client_opts.connections)
+                .map(|_| perf::PerfMode::Client { runs: runs.clone() })
+                .collect();
+
+            let address = client_opts.server_address.clone();
+            let layer = client_opts.transport_layer;
+            client = Some(client_opts);
+            (nodes, address, layer)
         }
-        Command::ClientSubstream(client_opts) => {
-            let (perf, handle) = perf::Perf::new(perf::PerfMode::ClientSubstream {
+        Command::ClientSubstream(client_opts) => (
+            vec![perf::PerfMode::ClientSubstream {
                 substreams: client_opts.substreams,
-            });
-
-            (
-                perf,
-                handle,
-                client_opts.server_address,
+                pattern: client_opts.pattern,
+            }],
+            client_opts.server_address,
+            client_opts.transport_layer,
+        ),
+        Command::PeerId(key_opts) => {
+            let keypair = litep2p_perf::keypair(&key_opts.load()?)?;
+            println!("{}", keypair.public().to_peer_id());
+            return Ok(());
+        }
+        Command::GenerateKey(opts) => {
+            let keypair = litep2p::crypto::ed25519::Keypair::generate();
+            utils::NodeKey::from_seed(keypair.secret().to_bytes())
+                .write(&opts.key_file, &keypair.public().to_bytes())?;
+            println!("{}", keypair.public().to_peer_id());
+            return Ok(());
+        }
+        Command::Loopback(client_opts) => {
+            let result = litep2p_perf::loopback(
                 client_opts.transport_layer,
+                &client_opts.schedule(),
+                client_opts.connections,
             )
+            .await;
+            match result {
+                Ok(stats) => client_opts.report(perf::IMPLEMENTATION, stats),
+                Err(error) => {
+                    if output == utils::OutputFormat::Json {
+                        client_opts.failed(perf::IMPLEMENTATION, &error).print();
+                    }
+                    return Err(error);
+                }
+            }
+            return Ok(());
         }
     };
 
-    let mut litep2p_config = litep2p::config::ConfigBuilder::new();
+    let mut clients = Vec::with_capacity(nodes.len());
+    for mode in nodes {
+        let (mut litep2p, perf_handle) = litep2p_perf::client_node(layer, mode)?;
 
-    match layer {
-        utils::TransportLayer::Tcp => {
-            litep2p_config = litep2p_config.with_tcp(litep2p::transport::tcp::config::Config {
-                reuse_port: true,
-                nodelay: true,
-                ..Default::default()
-            });
-        }
-        utils::TransportLayer::WebSocket => {
-            litep2p_config =
-                litep2p_config.with_websocket(litep2p::transport::websocket::config::Config {
-                    ..Default::default()
-                });
-        }
-        utils::TransportLayer::WebRTC => {
-            litep2p_config =
-                litep2p_config.with_webrtc(litep2p::transport::webrtc::config::Config {
-                    ..Default::default()
-                });
-        }
+        let dial = std::time::Instant::now();
+        litep2p.dial_address(server_address.parse()?).await?;
+        clients.push(litep2p_perf::run_client(litep2p, perf_handle, dial));
     }
 
-    let litep2p_config = litep2p_config
-        .with_user_protocol(Box::new(perf_client))
-        .build();
-
-    let mut litep2p = litep2p::Litep2p::new(litep2p_config)?;
-
-    litep2p.dial_address(server_address.parse()?).await?;
-
-    loop {
-        tokio::select! {
-            event = litep2p.next_event() => {
-                if let Some(event) = event {
-                    tracing::info!("Event: {event:?}");
+    let mut connections = Vec::with_capacity(clients.len());
+    for result in futures::future::join_all(clients).await {
+        match result {
+            Ok(connection) => connections.push(connection),
+            Err(error) => {
+                if let Some(client_opts) = client.as_ref() {
+                    if output == utils::OutputFormat::Json {
+                        client_opts.failed(perf::IMPLEMENTATION, &error).print();
+                    }
                 }
-            }
-            _ = &mut perf_handle => {
-                break;
+                return Err(error.into());
             }
         }
     }
 
+    // Each connection ran the same runs, aggregate them run by run.
+    let stats = utils::RunStats::aggregate_runs(connections);
+    match client {
+        Some(client_opts) => client_opts.report(perf::IMPLEMENTATION, stats),
+        None => stats.iter().for_each(utils::log_setup),
+    }
+
     Ok(())
 }
@@ -1,120 +1,137 @@
 use futures::StreamExt;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_util::compat::TokioAsyncReadCompatExt;
 
 use litep2p::{
     codec::ProtocolCodec,
-    protocol::{TransportEvent, TransportService, UserProtocol},
-    substream::Substream,
-    ProtocolName,
+    error::SubstreamError,
+    protocol::{Direction, TransportEvent, TransportService, UserProtocol},
+    types::SubstreamId,
+    PeerId, ProtocolName,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Instant,
+};
+use utils::{
+    LatencyHistogram, OpenPattern, PerfError, PerfProtocol, ProtocolVersion, RunParams, RunStats,
+    TransferOpts, UsageMeter,
 };
 
-const PROTOCOL_NAME: &str = "/litep2p-perf/1.0.0";
+const PROTOCOL_NAME: &str = "/litep2p-perf/2.0.0";
+const PROTOCOL_NAME_V1: &str = "/litep2p-perf/1.0.0";
+const PROTOCOL_NAME_PERF: &str = "/perf/1.0.0";
 const LOG_TARGET: &str = "litep2p-perf";
 
-#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+/// Name of this implementation in the reports exchanged with the remote.
+pub const IMPLEMENTATION: &str = "litep2p";
+
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub enum PerfMode {
-    Server,
-    Client {
-        upload_bytes: u64,
-        download_bytes: u64,
+    Server {
+        verify: bool,
+        transfer: TransferOpts,
     },
+    /// Execute the runs one after the other, each on a new substream.
+    Client { runs: Vec<RunParams> },
+    /// Open substreams without using them and report how long each took to open.
     ClientSubstream {
         substreams: usize,
+        pattern: OpenPattern,
     },
 }
 
+pub type ClientResult = Result<Vec<RunStats>, PerfError>;
+
 pub struct Perf {
     mode: PerfMode,
-    tx: Option<tokio::sync::oneshot::Sender<()>>,
+    tx: Option<tokio::sync::oneshot::Sender<ClientResult>>,
+    /// Transport services of `/litep2p-perf/1.0.0` and `/perf/1.0.0`, handed over by the
+    /// [`PerfAlias`]es.
+    aliases: Option<(
+        tokio::sync::oneshot::Receiver<TransportService>,
+        tokio::sync::oneshot::Receiver<TransportService>,
+    )>,
 }
 
-impl Perf {
-    pub fn new(mode: PerfMode) -> (Self, tokio::sync::oneshot::Receiver<()>) {
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        (Self { mode, tx: Some(tx) }, rx)
-    }
-
-    async fn read_u64(substream: &mut Substream) -> litep2p::Result<u64> {
-        let mut buf = [0u8; 8];
-        substream.read_exact(&mut buf).await?;
-        Ok(u64::from_be_bytes(buf))
-    }
+/// Registers one more perf protocol next to [`Perf`].
+///
+/// User protocols cannot declare fallback names, so `/litep2p-perf/1.0.0` and `/perf/1.0.0` are
+/// registered on their own and pass their transport service to [`Perf`], which serves all of them.
+pub struct PerfAlias {
+    protocol: &'static str,
+    tx: tokio::sync::oneshot::Sender<TransportService>,
+}
 
-    async fn write_u64(substream: &mut Substream, value: u64) -> litep2p::Result<()> {
-        substream.write_all(&value.to_be_bytes()).await?;
-        Ok(())
-    }
+/// Transport services of the protocols served by [`Perf`].
+struct Services {
+    v2: TransportService,
+    v1: TransportService,
+    perf: TransportService,
+}
 
-    async fn recv_bytes(substream: &mut Substream, to_recv: u64) -> litep2p::Result<()> {
-        let mut buf = vec![0u8; 1024];
-        let mut total = 0;
-        while total < to_recv {
-            let n = substream.read(&mut buf).await?;
-            if n == 0 {
-                break;
-            }
-            total += n as u64;
+impl Services {
+    fn get(&mut self, version: ProtocolVersion) -> &mut TransportService {
+        match version {
+            ProtocolVersion::V1 => &mut self.v1,
+            ProtocolVersion::V2 => &mut self.v2,
+            ProtocolVersion::Perf => &mut self.perf,
         }
-        Ok(())
     }
 
-    async fn send_bytes(substream: &mut Substream, to_send: u64) -> litep2p::Result<()> {
-        let buf = vec![0u8; 1024];
-        let mut total = 0;
-        while total < to_send {
-            substream.write_all(&buf).await?;
-            total += buf.len() as u64;
+    /// The next event of any service, with the version of the protocol it belongs to.
+    async fn next(&mut self) -> (ProtocolVersion, Option<TransportEvent>) {
+        tokio::select! {
+            event = self.v2.next() => (ProtocolVersion::V2, event),
+            event = self.v1.next() => (ProtocolVersion::V1, event),
+            event = self.perf.next() => (ProtocolVersion::Perf, event),
         }
-        Ok(())
     }
+}
 
-    async fn server_mode(mut substream: Substream) -> litep2p::Result<()> {
-        // Step 1. Read the download bytes.
-        let to_recv = Self::read_u64(&mut substream).await?;
-        // Step 2. Receive the download bytes.
-        Self::recv_bytes(&mut substream, to_recv).await?;
-
-        // Step 3. Read the upload bytes.
-        let to_send = Self::read_u64(&mut substream).await?;
-        // Step 4. Send the upload bytes.
-        Self::send_bytes(&mut substream, to_send).await?;
+impl Perf {
+    pub fn new(
+        mode: PerfMode,
+    ) -> (
+        Self,
+        [PerfAlias; 2],
+        tokio::sync::oneshot::Receiver<ClientResult>,
+    ) {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let (v1_tx, v1_rx) = tokio::sync::oneshot::channel();
+        let (perf_tx, perf_rx) = tokio::sync::oneshot::channel();
+        (
+            Self {
+                mode,
+                tx: Some(tx),
+                aliases: Some((v1_rx, perf_rx)),
+            },
+            [
+                PerfAlias {
+                    protocol: PROTOCOL_NAME_V1,
+                    tx: v1_tx,
+                },
+                PerfAlias {
+                    protocol: PROTOCOL_NAME_PERF,
+                    tx: perf_tx,
+                },
+            ],
+            rx,
+        )
+    }
+}
 
-        Ok(())
+#[async_trait::async_trait]
+impl UserProtocol for PerfAlias {
+    fn protocol(&self) -> ProtocolName {
+        self.protocol.into()
     }
 
-    async fn client_mode(
-        mut substream: Substream,
-        upload_bytes: u64,
-        download_bytes: u64,
-    ) -> litep2p::Result<()> {
-        // Step 1. Send the upload bytes.
-        Self::write_u64(&mut substream, upload_bytes).await?;
-        // Step 2. Send the upload bytes.
-        let now = std::time::Instant::now();
-        Self::send_bytes(&mut substream, upload_bytes).await?;
-        let elapsed = now.elapsed();
-        tracing::info!(
-            target: LOG_TARGET,
-            "Uploaded {} bytes in {:.4}s bandwidth {}",
-            utils::format_bytes(upload_bytes as usize),
-            elapsed.as_secs_f64(),
-            utils::format_bandwidth(elapsed, upload_bytes as usize)
-        );
-
-        // Step 3. Send the download bytes.
-        Self::write_u64(&mut substream, download_bytes).await?;
-        // Step 4. Receive the download bytes.
-        let now = std::time::Instant::now();
-        Self::recv_bytes(&mut substream, download_bytes).await?;
-        let elapsed = now.elapsed();
-        tracing::info!(
-            target: LOG_TARGET,
-            "Downloaded {} bytes in {:.4}s bandwidth {}",
-            utils::format_bytes(download_bytes as usize),
-            elapsed.as_secs_f64(),
-            utils::format_bandwidth(elapsed, download_bytes as usize)
-        );
+    fn codec(&self) -> ProtocolCodec {
+        ProtocolCodec::Unspecified
+    }
 
+    async fn run(self: Box<Self>, service: TransportService) -> litep2p::Result<()> {
+        let _ = self.tx.send(service);
         Ok(())
     }
 }
@@ -129,80 +146,402 @@ impl UserProtocol for Perf {
         ProtocolCodec::Unspecified
     }
 
-    async fn run(mut self: Box<Self>, mut service: TransportService) -> litep2p::Result<()> {
-        let mut time_to_open = std::time::Instant::now();
-        let mut times = Vec::with_capacity(1024);
+    async fn run(mut self: Box<Self>, service: TransportService) -> litep2p::Result<()> {
+        let (v1, perf) = self.aliases.take().expect("run once");
+        let (Ok(v1), Ok(perf)) = (v1.await, perf.await) else {
+            tracing::error!(target: LOG_TARGET, "{PROTOCOL_NAME_V1} and {PROTOCOL_NAME_PERF} are not registered");
+            return Ok(());
+        };
+        let mut services = Services {
+            v2: service,
+            v1,
+            perf,
+        };
+
+        // When each outbound substream still opening was requested.
+        let mut requested: HashMap<SubstreamId, Instant> = HashMap::new();
+        let mut open_latency = LatencyHistogram::new();
+        let mut usage = UsageMeter::start();
 
-        let num_substreams = match self.mode {
-            PerfMode::Server => 0,
-            PerfMode::Client { .. } => 1,
-            PerfMode::ClientSubstream { substreams, .. } => substreams,
+        let (num_substreams, mut pending_runs) = match &self.mode {
+            PerfMode::Server { .. } => (0, VecDeque::new()),
+            PerfMode::Client { runs } => (0, runs.iter().copied().collect()),
+            PerfMode::ClientSubstream { substreams, .. } => (*substreams, VecDeque::new()),
+        };
+        let mut results = Vec::with_capacity(pending_runs.len());
+        // The run in progress and the measurements of its substreams that completed so far.
+        let mut current_run: Option<RunParams> = None;
+        let mut substreams = Vec::new();
+        let (run_tx, mut run_rx) = tokio::sync::mpsc::channel(1);
+        // Clients prefer v2 and switch to v1 for good once the server refuses v2.
+        let mut client_version = match pending_runs.front().map(|run: &RunParams| run.protocol) {
+            Some(PerfProtocol::Perf) => ProtocolVersion::Perf,
+            _ => ProtocolVersion::V2,
         };
+        let mut server: Option<PeerId> = None;
+        let mut next_request_id = 1;
 
         loop {
-            tokio::select! {
-                event = service.next() => match event {
-                    Some(TransportEvent::ConnectionEstablished { peer, .. }) => {
-
-                        for i in 0..num_substreams {
-                            match service.open_substream(peer) {
-                                Ok(_) => {},
-                                Err(e) => {
-                                    tracing::error!(target: LOG_TARGET, "open substream error: {:?} while opening iter={i}", e);
-                                    tokio::time::sleep(std::time::Duration::from_secs(60)).await;
-                                }
+            let (version, event) = tokio::select! {
+                (version, event) = services.next() => (version, event),
+                Some((peer, result)) = run_rx.recv() => {
+                    let tx = self.tx.take().expect("client runs report once");
+                    match result {
+                        Ok(stats) => substreams.push(stats),
+                        Err(e) => {
+                            tracing::error!(target: LOG_TARGET, "client mode error: {:?}", e);
+                            let _ = tx.send(Err(e));
+                            return Ok(());
+                        }
+                    }
+
+                    let run = current_run.expect("completed a substream without a run");
+                    if substreams.len() < run.parallel {
+                        self.tx = Some(tx);
+                        continue;
+                    }
+                    let mut stats = RunStats::aggregate(std::mem::take(&mut substreams));
+                    if run.parallel > 1 {
+                        utils::log_parallel(&stats);
+                    }
+                    let run_usage = usage.finish();
+                    utils::log_usage("Run", &run_usage, stats.uploaded + stats.downloaded);
+                    stats.usage = Some(run_usage);
+                    results.push(stats);
+
+                    let Some(next) = pending_runs.pop_front() else {
+                        let _ = tx.send(Ok(results));
+                        return Ok(());
+                    };
+                    current_run = Some(next);
+                    usage = UsageMeter::start();
+                    for _ in 0..next.parallel {
+                        let at = Instant::now();
+                        match services.get(client_version).open_substream(peer) {
+                            Ok(substream_id) => {
+                                requested.insert(substream_id, at);
+                            }
+                            Err(e) => {
+                                let _ = tx.send(Err(PerfError::Substream(e.to_string())));
+                                return Ok(());
                             }
                         }
-                        time_to_open = std::time::Instant::now();
                     }
-                    Some(TransportEvent::ConnectionClosed { peer }) => {
-                        tracing::info!(target: LOG_TARGET, ?peer, "connection closed");
+                    self.tx = Some(tx);
+                    continue;
+                }
+            };
+
+            match event {
+                // Every service reports the connection, substreams are opened with the preferred
+                // one once it knows the connection.
+                Some(TransportEvent::ConnectionEstablished { peer, .. })
+                    if version == client_version =>
+                {
+                    server = Some(peer);
+                    if matches!(self.mode, PerfMode::Client { .. }) {
+                        current_run = pending_runs.pop_front();
                     }
-                    Some(TransportEvent::SubstreamOpened {
-                        substream,
-                        direction,
-                        ..
-                    }) => {
-                        match self.mode {
-                            PerfMode::ClientSubstream { .. } => {
-                                match direction {
-                                    litep2p::protocol::Direction::Inbound => {}
-                                    litep2p::protocol::Direction::Outbound(..) => {
-                                        tracing::info!("Substream opened in {:?}", time_to_open.elapsed());
-
-                                        times.push(time_to_open.elapsed());
-                                        if times.len() == num_substreams {
-                                            let total = times.iter().sum::<std::time::Duration>();
-                                            let avg = total / num_substreams as u32;
-                                            tracing::info!("Average time to open substreams n={num_substreams}, avg={:?}", avg);
-                                            let _ = self.tx.unwrap().send(());
-                                            return Ok(());
-                                        }
-                                    }
-                                }
+                    let to_open = match (&self.mode, current_run) {
+                        (_, Some(run)) => run.parallel,
+                        (
+                            PerfMode::ClientSubstream {
+                                pattern: OpenPattern::Sequential,
+                                ..
+                            },
+                            None,
+                        ) => num_substreams.min(1),
+                        _ => num_substreams,
+                    };
+                    usage = UsageMeter::start();
+                    for i in 0..to_open {
+                        let at = Instant::now();
+                        match services.get(client_version).open_substream(peer) {
+                            Ok(substream_id) => {
+                                requested.insert(substream_id, at);
                             }
-                            PerfMode::Server => {
-                                tokio::spawn(async move {
-                                    if let Err(e) = Self::server_mode(substream).await {
-                                        tracing::error!(target: LOG_TARGET, "server mode error: {:?}", e);
-                                    }
-                                });
+                            Err(e) => {
+                                tracing::error!(target: LOG_TARGET, "open substream error: {:?} while opening iter={i}", e);
+                                tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+                            }
+                        }
+                    }
+                }
+                Some(TransportEvent::ConnectionClosed { peer })
+                    if version == ProtocolVersion::V2 =>
+                {
+                    tracing::info!(target: LOG_TARGET, ?peer, "connection closed");
+                }
+                Some(TransportEvent::SubstreamOpenFailure { error, .. }) => {
+                    if !matches!(self.mode, PerfMode::Client { .. }) {
+                        tracing::error!(target: LOG_TARGET, ?version, "open substream failure: {:?}", error);
+                        continue;
+                    }
+
+                    let fallback = match (version, &error, server) {
+                        (ProtocolVersion::V2, SubstreamError::NegotiationError(_), Some(peer)) => {
+                            tracing::info!(target: LOG_TARGET, "server does not support {PROTOCOL_NAME}, falling back to {PROTOCOL_NAME_V1}");
+                            client_version = ProtocolVersion::V1;
+                            let at = Instant::now();
+                            services.v1.open_substream(peer).map(|substream_id| {
+                                requested.insert(substream_id, at);
+                            })
+                        }
+                        _ => Err(error),
+                    };
+                    if let Err(e) = fallback {
+                        let tx = self.tx.take().expect("client runs report once");
+                        let _ = tx.send(Err(PerfError::Substream(e.to_string())));
+                        return Ok(());
+                    }
+                }
+                Some(TransportEvent::SubstreamOpened {
+                    peer,
+                    substream,
+                    direction,
+                    ..
+                }) => {
+                    // Time from the request of an outbound substream until it opened.
+                    let opened_in = match direction {
+                        Direction::Outbound(substream_id) => requested
+                            .remove(&substream_id)
+                            .map(|requested_at| requested_at.elapsed()),
+                        Direction::Inbound => None,
+                    };
+                    match &self.mode {
+                        PerfMode::ClientSubstream {
+                            substreams,
+                            pattern,
+                        } => {
+                            let Some(opened_in) = opened_in else {
+                                continue;
+                            };
+                            tracing::info!("Substream opened in {:?}", opened_in);
+
+                            open_latency.record(opened_in);
+                            if open_latency.len() == *substreams as u64 {
+                                tracing::info!(
+                                    "Average time to open substreams n={substreams}, avg={:?}",
+                                    open_latency.mean()
+                                );
+                                utils::log_histogram("Substream open latency", &open_latency);
+                                let _ = self.tx.take().unwrap().send(Ok(Vec::new()));
+                                return Ok(());
                             }
-                            PerfMode::Client { upload_bytes, download_bytes } => {
-                                let tx = self.tx.take().unwrap();
-                                tokio::spawn(async move {
-                                    if let Err(e) = Self::client_mode(substream, upload_bytes, download_bytes).await {
-                                        tracing::error!(target: LOG_TARGET, "client mode error: {:?}", e);
+                            if *pattern == OpenPattern::Sequential {
+                                let at = Instant::now();
+                                match services.get(client_version).open_substream(peer) {
+                                    Ok(substream_id) => {
+                                        requested.insert(substream_id, at);
                                     }
-                                    let _ = tx.send(());
-                                    return;
-                                });
-                            },
+                                    Err(e) => {
+                                        tracing::error!(target: LOG_TARGET, "open substream error: {:?}", e);
+                                    }
+                                }
+                            }
+                        }
+                        PerfMode::Server { verify, transfer } => {
+                            let (verify, transfer) = (*verify, *transfer);
+                            tokio::spawn(async move {
+                                if let Err(e) = utils::server_mode(
+                                    substream.compat(),
+                                    version,
+                                    verify,
+                                    transfer,
+                                    IMPLEMENTATION,
+                                )
+                                .await
+                                {
+                                    tracing::error!(target: LOG_TARGET, "server mode error: {:?}", e);
+                                }
+                            });
+                        }
+                        PerfMode::Client { .. } => {
+                            let run = current_run.expect("opened a substream without a run");
+                            let request_id = next_request_id;
+                            next_request_id += 1;
+                            let run_tx = run_tx.clone();
+                            let run = tokio::spawn(utils::client_mode(
+                                substream.compat(),
+                                version,
+                                request_id,
+                                run,
+                                IMPLEMENTATION,
+                            ));
+                            tokio::spawn(async move {
+                                // A panicked run fails the client instead of never reporting.
+                                let result = match run.await {
+                                    Ok(result) => result.map(|stats| RunStats {
+                                        substream_setup: opened_in,
+                                        ..stats
+                                    }),
+                                    Err(error) => Err(PerfError::Task(error.to_string())),
+                                };
+                                let _ = run_tx.send((peer, result)).await;
+                            });
                         }
                     }
-                    _ => {},
-                },
+                }
+                _ => {}
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use upstream_libp2p::{noise, swarm::SwarmEvent, tcp, yamux, SwarmBuilder};
+    use utils::Workload;
+
+    const UPLOAD: u64 = 1024 * 1024 + 3;
+    const DOWNLOAD: u64 = 2 * 1024 * 1024 + 5;
+
+    fn litep2p_node(
+        mode: PerfMode,
+    ) -> (
+        litep2p::Litep2p,
+        tokio::sync::oneshot::Receiver<ClientResult>,
+    ) {
+        let (perf, aliases, handle) = Perf::new(mode);
+        let mut config = litep2p::config::ConfigBuilder::new()
+            .with_tcp(litep2p::transport::tcp::config::Config {
+                listen_addresses: vec!["/ip4/127.0.0.1/tcp/0".parse().unwrap()],
+                ..Default::default()
+            })
+            .with_user_protocol(Box::new(perf));
+        for alias in aliases {
+            config = config.with_user_protocol(Box::new(alias));
+        }
+        (litep2p::Litep2p::new(config.build()).unwrap(), handle)
+    }
+
+    #[tokio::test]
+    async fn client_against_upstream_server() {
+        let mut server = SwarmBuilder::with_new_identity()
+            .with_tokio()
+            .with_tcp(
+                tcp::Config::default(),
+                noise::Config::new,
+                yamux::Config::default,
+            )
+            .unwrap()
+            .with_behaviour(|_| upstream_perf::server::Behaviour::new())
+            .unwrap()
+            .with_swarm_config(|config| {
+                config.with_idle_connection_timeout(Duration::from_secs(60))
+            })
+            .build();
+        server
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+        let address = loop {
+            if let SwarmEvent::NewListenAddr { address, .. } = server.select_next_some().await {
+                break address;
+            }
+        };
+        let server_address = format!("{address}/p2p/{}", server.local_peer_id());
+        let (server_tx, mut server_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                if let SwarmEvent::Behaviour(event) = server.select_next_some().await {
+                    let _ = server_tx.send(event.stats);
+                }
+            }
+        });
+
+        let run = RunParams {
+            workload: Workload::Bytes {
+                upload: UPLOAD,
+                download: DOWNLOAD,
+            },
+            verify: false,
+            transfer: TransferOpts::default(),
+            duplex: false,
+            parallel: 1,
+            protocol: PerfProtocol::Perf,
+        };
+        let (mut client, mut handle) = litep2p_node(PerfMode::Client { runs: vec![run] });
+        client
+            .dial_address(server_address.parse().unwrap())
+            .await
+            .unwrap();
+        let stats = loop {
+            tokio::select! {
+                _ = client.next_event() => {}
+                result = &mut handle => break result.unwrap().unwrap(),
+            }
+        };
+
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].uploaded, UPLOAD);
+        assert_eq!(stats[0].downloaded, DOWNLOAD);
+        let server_run = server_rx.recv().await.unwrap();
+        assert_eq!(server_run.params.to_receive as u64, UPLOAD);
+        assert_eq!(server_run.params.to_send as u64, DOWNLOAD);
+    }
+
+    #[tokio::test]
+    async fn server_against_upstream_client() {
+        let (mut server, _handle) = litep2p_node(PerfMode::Server {
+            verify: false,
+            transfer: TransferOpts::default(),
+        });
+        let address = server.listen_addresses().next().unwrap().clone();
+        let server_peer_id = server.local_peer_id().to_string().parse().unwrap();
+        tokio::spawn(async move { while server.next_event().await.is_some() {} });
+
+        let mut client = SwarmBuilder::with_new_identity()
+            .with_tokio()
+            .with_tcp(
+                tcp::Config::default(),
+                noise::Config::new,
+                yamux::Config::default,
+            )
+            .unwrap()
+            .with_behaviour(|_| upstream_perf::client::Behaviour::new())
+            .unwrap()
+            .with_swarm_config(|config| {
+                config.with_idle_connection_timeout(Duration::from_secs(60))
+            })
+            .build();
+        client
+            .dial(
+                address
+                    .to_string()
+                    .parse::<upstream_libp2p::Multiaddr>()
+                    .unwrap(),
+            )
+            .unwrap();
+
+        let duration = loop {
+            match client.select_next_some().await {
+                SwarmEvent::ConnectionEstablished { .. } => {
+                    client
+                        .behaviour_mut()
+                        .perf(
+                            server_peer_id,
+                            upstream_perf::RunParams {
+                                to_send: UPLOAD as usize,
+                                to_receive: DOWNLOAD as usize,
+                            },
+                        )
+                        .unwrap();
+                }
+                SwarmEvent::Behaviour(upstream_perf::client::Event { result, .. }) => {
+                    if let upstream_perf::RunUpdate::Final(run) = result.unwrap() {
+                        break run.duration;
+                    }
+                }
+                _ => {}
+            }
+        };
+
+        assert!(!duration.upload.is_zero());
+        assert!(!duration.download.is_zero());
+    }
+}
@@ -0,0 +1,487 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{Bandwidth, ByteSize, Metric, PerfResult, Requested, UnitSystem};
+
+/// Colors of the lines of the charts, one per implementation pair.
+const PALETTE: [&str; 6] = [
+    "#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b",
+];
+
+const CHART_WIDTH: f64 = 800.0;
+const CHART_HEIGHT: f64 = 480.0;
+/// Space left of, right of, above and below the plot area.
+const MARGIN: (f64, f64, f64, f64) = (90.0, 30.0, 40.0, 90.0);
+const Y_TICKS: usize = 5;
+/// Room left for the legend, from its line to the end of the longest title.
+const LEGEND_WIDTH: f64 = 150.0;
+
+/// Client and server implementations of a run.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Pair {
+    pub client: String,
+    /// `None` when the server did not report its implementation.
+    pub server: Option<String>,
+}
+
+impl Pair {
+    fn homogeneous(&self) -> bool {
+        self.server.as_deref() == Some(self.client.as_str())
+    }
+
+    /// Column header of the pair, such as `Litep2p->Libp2p`.
+    fn title(&self) -> String {
+        let server = self.server.as_deref().unwrap_or("?");
+        format!("{}->{}", capitalize(&self.client), capitalize(server))
+    }
+}
+
+impl std::fmt::Display for Pair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} -> {}",
+            self.client,
+            self.server.as_deref().unwrap_or("?")
+        )
+    }
+}
+
+fn capitalize(name: &str) -> String {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// Throughput of each implementation pair for each size of the fixed size runs and each duration
+/// of the time bounded runs over one transport, the data behind the README tables and charts.
+///
+/// Only runs over a single substream of a single connection, neither duplex nor warmup, are
+/// taken. Each cell is the median throughput of the runs of the pair and size, the steady-state
+/// throughput for a duration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandwidthReport {
+    pub transport: String,
+    /// Homogeneous pairs first, then the mixed ones.
+    pub pairs: Vec<Pair>,
+    /// Sizes of all pairs and directions, from the smallest.
+    pub sizes: Vec<u64>,
+    /// Durations of all pairs and directions in milliseconds, from the shortest.
+    pub durations: Vec<u64>,
+    /// Median throughput in bytes per second of each direction, pair and size or duration.
+    cells: BTreeMap<Cell, f64>,
+}
+
+/// Direction, pair and size or duration of a cell of the tables.
+type Cell = (Metric, Pair, Requested);
+
+impl BandwidthReport {
+    /// One report per transport of the results.
+    pub fn new(results: &[PerfResult]) -> Vec<BandwidthReport> {
+        let mut samples: BTreeMap<String, BTreeMap<Cell, Vec<f64>>> = BTreeMap::new();
+        for result in results.iter().filter(|result| {
+            (result.mode == "bytes" || result.mode == "duration")
+                && result.error.is_none()
+                && !result.warmup
+                && !result.duplex
+                && result.parallel == 1
+                && result.connections == 1
+        }) {
+            let pair = Pair {
+                client: result.implementation.clone(),
+                server: result.server_implementation.clone(),
+            };
+            for (metric, direction) in [
+                (Metric::Upload, &result.upload),
+                (Metric::Download, &result.download),
+            ] {
+                let requested = direction.requested();
+                if requested.is_empty() {
+                    continue;
+                }
+                samples
+                    .entry(result.transport.clone())
+                    .or_default()
+                    .entry((metric, pair.clone(), requested))
+                    .or_default()
+                    .push(direction.throughput());
+            }
+        }
+
+        samples
+            .into_iter()
+            .map(|(transport, samples)| {
+                let mut pairs: Vec<Pair> =
+                    samples.keys().map(|(_, pair, _)| pair.clone()).collect();
+                pairs.sort_by_key(|pair| (!pair.homogeneous(), pair.clone()));
+                pairs.dedup();
+                let (mut sizes, mut durations) = (Vec::new(), Vec::new());
+                for (_, _, requested) in samples.keys() {
+                    match *requested {
+                        Requested::Bytes(size) => sizes.push(size),
+                        Requested::Milliseconds(milliseconds) => durations.push(milliseconds),
+                    }
+                }
+                sizes.sort_unstable();
+                sizes.dedup();
+                durations.sort_unstable();
+                durations.dedup();
+                let cells = samples
+                    .into_iter()
+                    .map(|(key, values)| (key, median(values)))
+                    .collect();
+
+                BandwidthReport {
+                    transport,
+                    pairs,
+                    sizes,
+                    durations,
+                    cells,
+                }
+            })
+            .collect()
+    }
+
+    /// Median throughput in bytes per second of a direction of a pair for a size.
+    pub fn throughput(&self, metric: Metric, pair: &Pair, size: u64) -> Option<f64> {
+        self.cells
+            .get(&(metric, pair.clone(), Requested::Bytes(size)))
+            .copied()
+    }
+
+    /// Median steady-state throughput in bytes per second of a direction of a pair for a
+    /// duration in milliseconds.
+    pub fn timed_throughput(&self, metric: Metric, pair: &Pair, milliseconds: u64) -> Option<f64> {
+        self.cells
+            .get(&(metric, pair.clone(), Requested::Milliseconds(milliseconds)))
+            .copied()
+    }
+
+    /// Markdown table of the throughput of each pair, the uploads first and then the downloads.
+    pub fn table(&self, units: UnitSystem) -> String {
+        let rows: Vec<_> = self
+            .sizes
+            .iter()
+            .map(|&size| (Requested::Bytes(size), ByteSize(size).format(units)))
+            .collect();
+        self.table_of("Bytes     ", &rows, units)
+    }
+
+    /// Markdown table of the steady-state throughput of each pair in the time bounded runs, the
+    /// uploads first and then the downloads.
+    pub fn timed_table(&self, units: UnitSystem) -> String {
+        let rows: Vec<_> = self
+            .durations
+            .iter()
+            .map(|&milliseconds| {
+                let requested = Requested::Milliseconds(milliseconds);
+                (requested, requested.to_string())
+            })
+            .collect();
+        self.table_of("Duration  ", &rows, units)
+    }
+
+    fn table_of(&self, column: &str, rows: &[(Requested, String)], units: UnitSystem) -> String {
+        let mut table = format!("| Operation  | {column} |");
+        let mut separator = String::from("|------------|------------|");
+        for pair in &self.pairs {
+            let title = pair.title();
+            let _ = write!(table, " {title} |");
+            let _ = write!(separator, "{}|", "-".repeat(title.len() + 2));
+        }
+        let _ = writeln!(table);
+        table.push_str(&separator);
+        table.push('\n');
+
+        for (metric, operation) in [
+            (Metric::Upload, "Uploaded  "),
+            (Metric::Download, "Downloaded"),
+        ] {
+            for (requested, label) in rows {
+                let cell = |pair: &Pair| self.cells.get(&(metric, pair.clone(), *requested));
+                if self.pairs.iter().all(|pair| cell(pair).is_none()) {
+                    continue;
+                }
+                let _ = write!(table, "| {operation} | {label} |");
+                for pair in &self.pairs {
+                    let cell = cell(pair)
+                        .map_or("-".to_string(), |&value| format_throughput(value, units));
+                    let _ = write!(table, " {cell} |");
+                }
+                table.push('\n');
+            }
+        }
+        table
+    }
+
+    /// Markdown bullets comparing the homogeneous pairs and giving the top throughput of each
+    /// pair.
+    ///
+    /// Pairs are compared on the geometric mean of the ratio of their throughputs over the sizes
+    /// both measured.
+    pub fn summary(&self, units: UnitSystem) -> String {
+        let mut summary = String::new();
+        let homogeneous: Vec<_> = self
+            .pairs
+            .iter()
+            .filter(|pair| pair.homogeneous())
+            .collect();
+        for (index, first) in homogeneous.iter().enumerate() {
+            for second in &homogeneous[index + 1..] {
+                // Lead with the faster implementation, as the README always did.
+                let (first, second) = match self.relative_change(Metric::Upload, first, second) {
+                    Some(change) if change < 0.0 => (*second, *first),
+                    _ => (*first, *second),
+                };
+                let changes: Vec<_> = [Metric::Upload, Metric::Download]
+                    .into_iter()
+                    .filter_map(|metric| {
+                        let change = self.relative_change(metric, first, second)?;
+                        let word = if change >= 0.0 { "faster" } else { "slower" };
+                        Some(format!(
+                            "**~{:.0}% {word}** for {metric} speeds",
+                            change.abs() * 100.0
+                        ))
+                    })
+                    .collect();
+                if !changes.is_empty() {
+                    let _ = writeln!(
+                        summary,
+                        "- {} is {} than {}",
+                        first.client,
+                        changes.join(", and "),
+                        second.client
+                    );
+                }
+            }
+        }
+
+        for pair in &self.pairs {
+            let tops: Vec<_> = [Metric::Upload, Metric::Download]
+                .into_iter()
+                .filter_map(|metric| {
+                    let (size, value) = self.top(metric, pair)?;
+                    Some(format!(
+                        "top {metric} bandwidth is `{}` ({})",
+                        format_throughput(value, units),
+                        ByteSize(size).format(units)
+                    ))
+                })
+                .collect();
+            if !tops.is_empty() {
+                let _ = writeln!(summary, "- {pair}: {}", tops.join(", "));
+            }
+        }
+        summary
+    }
+
+    /// How much faster `first` is than `second` in a direction, `0.1` for 10% faster.
+    fn relative_change(&self, metric: Metric, first: &Pair, second: &Pair) -> Option<f64> {
+        let log_ratios: Vec<f64> = self
+            .sizes
+            .iter()
+            .filter_map(|&size| {
+                let first = self.throughput(metric, first, size)?;
+                let second = self.throughput(metric, second, size)?;
+                (first > 0.0 && second > 0.0).then(|| (first / second).ln())
+            })
+            .collect();
+        if log_ratios.is_empty() {
+            return None;
+        }
+        Some((log_ratios.iter().sum::<f64>() / log_ratios.len() as f64).exp() - 1.0)
+    }
+
+    /// The size with the highest throughput of a direction of a pair.
+    fn top(&self, metric: Metric, pair: &Pair) -> Option<(u64, f64)> {
+        self.sizes
+            .iter()
+            .filter_map(|&size| Some((size, self.throughput(metric, pair, size)?)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// SVG line chart of the throughput of a direction against the size, one line per pair,
+    /// with the sizes on a logarithmic scale.
+    pub fn chart(&self, metric: Metric, units: UnitSystem) -> String {
+        let (left, right, top, bottom) = MARGIN;
+        let (width, height) = (CHART_WIDTH - left - right, CHART_HEIGHT - top - bottom);
+
+        let max = self
+            .cells
+            .iter()
+            .filter(|((cell_metric, _, requested), _)| {
+                *cell_metric == metric && matches!(requested, Requested::Bytes(_))
+            })
+            .map(|(_, value)| *value * 8.0)
+            .fold(0.0, f64::max);
+        // Round the ticks in the prefix of the largest throughput, so that they print as whole
+        // numbers with binary prefixes too.
+        let prefix = units.base().powf(max.max(1.0).log(units.base()).floor());
+        let step = nice_step(max / prefix / Y_TICKS as f64) * prefix;
+        let y_max = step * (max / step).ceil().max(1.0);
+
+        let (min_size, max_size) = (
+            self.sizes.first().copied().unwrap_or(1) as f64,
+            self.sizes.last().copied().unwrap_or(1) as f64,
+        );
+        let x = |size: u64| {
+            let span = (max_size.log2() - min_size.log2()).max(f64::EPSILON);
+            left + ((size as f64).log2() - min_size.log2()) / span * width
+        };
+        let y = |bits_per_second: f64| top + height - bits_per_second / y_max * height;
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{CHART_WIDTH}" height="{CHART_HEIGHT}" viewBox="0 0 {CHART_WIDTH} {CHART_HEIGHT}" font-family="sans-serif" font-size="12">"#
+        );
+        let _ = writeln!(
+            svg,
+            r#"<rect width="{CHART_WIDTH}" height="{CHART_HEIGHT}" fill="white"/>"#
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{:.1}" y="24" text-anchor="middle" font-size="16">{} bandwidth over {}</text>"#,
+            CHART_WIDTH / 2.0,
+            capitalize(&metric.to_string()),
+            self.transport
+        );
+
+        // Horizontal grid lines with the throughput of each.
+        for tick in 0..=(y_max / step).round() as usize {
+            let value = tick as f64 * step;
+            let _ = writeln!(
+                svg,
+                r##"<line x1="{left:.1}" y1="{0:.1}" x2="{1:.1}" y2="{0:.1}" stroke="#dddddd"/>"##,
+                y(value),
+                left + width
+            );
+            let _ = writeln!(
+                svg,
+                r#"<text x="{:.1}" y="{:.1}" text-anchor="end">{}</text>"#,
+                left - 8.0,
+                y(value) + 4.0,
+                Bandwidth::from_bits_per_second(value).format(units)
+            );
+        }
+        // One label per size, below the plot area.
+        for &size in &self.sizes {
+            let _ = writeln!(
+                svg,
+                r#"<text x="{0:.1}" y="{1:.1}" text-anchor="end" transform="rotate(-45 {0:.1} {1:.1})">{2}</text>"#,
+                x(size),
+                top + height + 16.0,
+                ByteSize(size).format(units)
+            );
+        }
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{left:.1}" y="{top:.1}" width="{width:.1}" height="{height:.1}" fill="none" stroke="black"/>"#
+        );
+
+        for (index, pair) in self.pairs.iter().enumerate() {
+            let color = PALETTE[index % PALETTE.len()];
+            let points: Vec<_> = self
+                .sizes
+                .iter()
+                .filter_map(|&size| {
+                    let value = self.throughput(metric, pair, size)? * 8.0;
+                    Some(format!("{:.1},{:.1}", x(size), y(value)))
+                })
+                .collect();
+            if !points.is_empty() {
+                let _ = writeln!(
+                    svg,
+                    r#"<polyline points="{}" fill="none" stroke="{color}" stroke-width="2"/>"#,
+                    points.join(" ")
+                );
+            }
+
+            // Legend in the bottom right corner of the plot area, below the lines of
+            // throughputs of the same order.
+            let legend_y = top + height - 16.0 - (self.pairs.len() - 1 - index) as f64 * 18.0;
+            let legend_x = left + width - LEGEND_WIDTH;
+            let _ = writeln!(
+                svg,
+                r#"<line x1="{legend_x:.1}" y1="{legend_y:.1}" x2="{:.1}" y2="{legend_y:.1}" stroke="{color}" stroke-width="2"/>"#,
+                legend_x + 24.0
+            );
+            let _ = writeln!(
+                svg,
+                r#"<text x="{:.1}" y="{:.1}">{}</text>"#,
+                legend_x + 30.0,
+                legend_y + 4.0,
+                pair.title()
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// File name of the chart of a direction, such as `tcp-upload.svg`.
+    pub fn chart_name(&self, metric: Metric) -> String {
+        format!("{}-{metric}.svg", self.transport)
+    }
+
+    /// Markdown section with the charts, the summary and the table, linking the charts from
+    /// `chart_dir` when they were written.
+    pub fn markdown(&self, units: UnitSystem, chart_dir: Option<&str>) -> String {
+        let mut markdown = format!("### Bandwidth over {}\n\n", self.transport);
+        if let Some(dir) = chart_dir.filter(|_| !self.sizes.is_empty()) {
+            for metric in [Metric::Upload, Metric::Download] {
+                let _ = writeln!(
+                    markdown,
+                    "![{} bandwidth over {}]({dir}/{})\n",
+                    capitalize(&metric.to_string()),
+                    self.transport,
+                    self.chart_name(metric)
+                );
+            }
+        }
+        let summary = self.summary(units);
+        if !summary.is_empty() {
+            markdown.push_str(&summary);
+            markdown.push('\n');
+        }
+        if !self.sizes.is_empty() {
+            markdown.push_str(&self.table(units));
+        }
+        if !self.durations.is_empty() {
+            if !self.sizes.is_empty() {
+                markdown.push('\n');
+            }
+            markdown.push_str("Steady-state throughput of the time bounded runs:\n\n");
+            markdown.push_str(&self.timed_table(units));
+        }
+        markdown
+    }
+}
+
+/// Throughput given in bytes per second, as in the README tables.
+fn format_throughput(bytes_per_second: f64, units: UnitSystem) -> String {
+    Bandwidth::from_bits_per_second(bytes_per_second * 8.0).format(units)
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let n = values.len();
+    if n.is_multiple_of(2) {
+        (values[n / 2 - 1] + values[n / 2]) / 2.0
+    } else {
+        values[n / 2]
+    }
+}
+
+/// The smallest of 1, 2 and 5 times a power of ten at least `step`.
+fn nice_step(step: f64) -> f64 {
+    if step <= 0.0 {
+        return 1.0;
+    }
+    let magnitude = 10f64.powf(step.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|factor| factor * magnitude)
+        .find(|&nice| nice >= step)
+        .unwrap_or(10.0 * magnitude)
+}
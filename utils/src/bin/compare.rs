@@ -0,0 +1,86 @@
+//! Compare two sets of JSON perf results and flag the significant changes.
+//!
+//! Exits with status 2 when a case regressed, and with status 1 when the results could not be
+//! compared.
+
+use std::path::PathBuf;
+
+use clap::Parser as ClapParser;
+
+use utils::{PerfResult, Significance, UnitSystem, Verdict};
+
+/// Exit status when a case regressed, distinct from the status 1 of an error.
+const REGRESSION_EXIT_CODE: i32 = 2;
+
+/// Compare the results printed by clients with `--output json`, such as before and after
+/// bumping a dependency.
+#[derive(Debug, ClapParser)]
+struct CompareOpts {
+    /// Results of the reference build, one JSON object per line.
+    baseline: PathBuf,
+
+    /// Results of the build under test, one JSON object per line.
+    candidate: PathBuf,
+
+    /// Largest p-value of the Mann-Whitney U test that counts as significant.
+    #[clap(long, default_value = "0.05")]
+    alpha: f64,
+
+    /// Smallest change of the median, in percent, that counts as a regression or an
+    /// improvement.
+    #[clap(long, default_value = "5")]
+    threshold: f64,
+
+    /// The prefixes used to print bandwidths.
+    #[clap(long, default_value = "si")]
+    units: UnitSystem,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let opts = CompareOpts::parse();
+    utils::set_units(opts.units);
+
+    let baseline = PerfResult::load(&opts.baseline)?;
+    let candidate = PerfResult::load(&opts.candidate)?;
+    let (comparisons, unmatched) = utils::compare(
+        &baseline,
+        &candidate,
+        Significance {
+            alpha: opts.alpha,
+            threshold: opts.threshold,
+        },
+    );
+
+    println!("| Case | Metric | Baseline | Candidate | Change | p-value | Verdict |");
+    println!("|------|--------|----------|-----------|--------|---------|---------|");
+    for comparison in &comparisons {
+        println!(
+            "| {} | {} | {} (n={}) | {} (n={}) | {:+.2}% | {:.4} | {} |",
+            comparison.case,
+            comparison.metric,
+            comparison.metric.format(comparison.baseline_median()),
+            comparison.baseline.len(),
+            comparison.metric.format(comparison.candidate_median()),
+            comparison.candidate.len(),
+            comparison.change,
+            comparison.p_value,
+            comparison.verdict,
+        );
+    }
+    for case in &unmatched.baseline {
+        println!("Only in the baseline: {case}");
+    }
+    for case in &unmatched.candidate {
+        println!("Only in the candidate: {case}");
+    }
+
+    let regressions = comparisons
+        .iter()
+        .filter(|comparison| comparison.verdict == Verdict::Regression)
+        .count();
+    if regressions > 0 {
+        eprintln!("{regressions} regression(s) found");
+        std::process::exit(REGRESSION_EXIT_CODE);
+    }
+    Ok(())
+}
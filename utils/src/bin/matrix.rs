@@ -0,0 +1,473 @@
+//! Run the bandwidth benchmark over every client and server implementation pair.
+//!
+//! The release binaries are built once, each server is started once per transport and waited
+//! for until it announces its listen addresses, and every client runs against it with a
+//! timeout. Servers and hung clients are killed whatever the outcome of a cell.
+
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use clap::{Parser as ClapParser, ValueEnum};
+
+use utils::{ByteSize, ClientOpts, PerfResult, ServerReady, TransportLayer, UnitSystem};
+
+/// How many of the last lines a server logged are shown when it exits before listening.
+const SERVER_LOG_LINES: usize = 20;
+
+/// The node key the servers are started with.
+const NODE_KEY: &str = "secret";
+
+/// How often a running client is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Run every client against every server, for each transport and size, and print the results
+/// as JSON lines, ready for the `report` and `compare` binaries.
+#[derive(Debug, ClapParser)]
+struct MatrixOpts {
+    /// Comma separated implementations, each one runs as client and as server.
+    #[clap(long, value_delimiter = ',', default_value = "litep2p,libp2p")]
+    implementations: Vec<Implementation>,
+
+    /// Comma separated transport layers.
+    ///
+    /// Cells whose client or server does not support the transport are skipped.
+    #[clap(long, value_delimiter = ',', default_value = "tcp")]
+    transports: Vec<TransportLayer>,
+
+    /// Comma separated sizes, each one uploaded and downloaded by a run.
+    #[clap(
+        long,
+        value_delimiter = ',',
+        default_value = "16MiB,32MiB,64MiB,128MiB,256MiB,512MiB,1GiB"
+    )]
+    sizes: Vec<ByteSize>,
+
+    /// Measured iterations of each size.
+    #[clap(long, default_value = "1")]
+    iterations: usize,
+
+    /// Discarded iterations of each size, before the measured ones.
+    #[clap(long, default_value = "0")]
+    warmup: usize,
+
+    /// Use the `litep2p-perf` and `libp2p-perf` binaries of this directory instead of building
+    /// the release binaries of the workspace.
+    #[clap(long)]
+    binaries: Option<PathBuf>,
+
+    /// Port of the first server, the following servers take the next ports.
+    #[clap(long, default_value = "33333")]
+    port: u16,
+
+    /// Seconds to wait for a server to listen.
+    #[clap(long, default_value = "30")]
+    ready_timeout: u64,
+
+    /// Seconds after which a client still running is killed and its cell failed.
+    #[clap(long, default_value = "600")]
+    cell_timeout: u64,
+
+    /// Write the results to this file instead of the standard output.
+    #[clap(long)]
+    output: Option<PathBuf>,
+
+    /// The prefixes used to print sizes and bandwidths in the progress lines.
+    #[clap(long, default_value = "si")]
+    units: UnitSystem,
+}
+
+/// An implementation of the perf protocols, with its binary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Implementation {
+    #[clap(name = "litep2p")]
+    Litep2p,
+
+    #[clap(name = "libp2p")]
+    Libp2p,
+}
+
+impl Implementation {
+    fn name(self) -> &'static str {
+        match self {
+            Implementation::Litep2p => "litep2p",
+            Implementation::Libp2p => "libp2p",
+        }
+    }
+
+    /// Name of the package and of its binary.
+    fn package(self) -> &'static str {
+        match self {
+            Implementation::Litep2p => "litep2p-perf",
+            Implementation::Libp2p => "libp2p-perf",
+        }
+    }
+
+    fn supports(self, transport: TransportLayer) -> bool {
+        match self {
+            Implementation::Litep2p => {
+                matches!(transport, TransportLayer::Tcp | TransportLayer::WebSocket)
+            }
+            Implementation::Libp2p => transport == TransportLayer::Tcp,
+        }
+    }
+}
+
+fn transport_name(transport: TransportLayer) -> &'static str {
+    match transport {
+        TransportLayer::Tcp => "tcp",
+        TransportLayer::WebSocket => "websocket",
+        TransportLayer::WebRTC => "webrtc",
+    }
+}
+
+/// The address a server listens on.
+fn listen_address(transport: TransportLayer, port: u16) -> Result<String, String> {
+    match transport {
+        TransportLayer::Tcp => Ok(format!("/ip4/127.0.0.1/tcp/{port}")),
+        TransportLayer::WebSocket => Ok(format!("/ip4/127.0.0.1/tcp/{port}/ws")),
+        // Dialing requires the certificate hash of the server.
+        TransportLayer::WebRTC => Err("webrtc is not supported by the matrix".to_string()),
+    }
+}
+
+/// A running server, killed when dropped.
+struct Server {
+    child: Child,
+}
+
+impl Server {
+    /// Start the server and wait until it announces that it listens.
+    fn start(
+        binary: &Path,
+        implementation: Implementation,
+        transport: TransportLayer,
+        address: &str,
+        timeout: Duration,
+    ) -> Result<(Self, ServerReady), String> {
+        let child = Command::new(binary)
+            .args([
+                "server",
+                "--listen-address",
+                address,
+                "--node-key",
+                NODE_KEY,
+            ])
+            .args(["--transport-layer", transport_name(transport)])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|error| format!("failed to start {}: {error}", binary.display()))?;
+        let mut server = Server { child };
+
+        // Keep the last lines of the logs to tell why a server exited before listening.
+        let stderr = server.child.stderr.take().expect("stderr is piped");
+        let logs = std::thread::spawn(move || {
+            let mut logs = VecDeque::with_capacity(SERVER_LOG_LINES);
+            for line in BufReader::new(stderr).lines() {
+                let Ok(line) = line else { break };
+                if logs.len() == SERVER_LOG_LINES {
+                    logs.pop_front();
+                }
+                logs.push_back(line);
+            }
+            Vec::from(logs).join("\n")
+        });
+
+        // Watch the output for the announcement, then keep draining it so the server never
+        // blocks on a full pipe.
+        let stdout = server.child.stdout.take().expect("stdout is piped");
+        let (ready_tx, ready_rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut ready_tx = Some(ready_tx);
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if let Some(ready) = ServerReady::parse(&line)
+                    && let Some(ready_tx) = ready_tx.take()
+                {
+                    let _ = ready_tx.send(ready);
+                }
+            }
+        });
+
+        match ready_rx.recv_timeout(timeout) {
+            Ok(ready) if !ready.listen_addresses.is_empty() => Ok((server, ready)),
+            Ok(_) => Err(format!(
+                "{} server announced no listen address",
+                implementation.name()
+            )),
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                // The standard output closed, the logs end once the server exited.
+                let _ = server.child.wait();
+                let logs = logs.join().unwrap_or_default();
+                Err(format!(
+                    "{} server exited before listening on {address}:\n{logs}",
+                    implementation.name()
+                ))
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(format!(
+                "{} server not listening on {address} after {timeout:?}",
+                implementation.name()
+            )),
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Run a client to completion, or kill it after `timeout`, and return its standard output.
+fn run_client(binary: &Path, args: &[String], timeout: Duration) -> Result<String, String> {
+    let mut child = Command::new(binary)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|error| format!("failed to start {}: {error}", binary.display()))?;
+
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let reader = std::thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stdout.read_to_string(&mut output);
+        output
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Ok(status),
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                break Err(format!("client killed after {timeout:?}"));
+            }
+            Ok(None) => std::thread::sleep(POLL_INTERVAL),
+            Err(error) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                break Err(format!("failed to wait for the client: {error}"));
+            }
+        }
+    };
+    let output = reader.join().unwrap_or_default();
+    match status {
+        Ok(status) if status.success() => Ok(output),
+        // A client failing in JSON mode still prints its failed result.
+        Ok(status) if output.lines().any(|line| line.starts_with('{')) => {
+            eprintln!("client exited with {status}");
+            Ok(output)
+        }
+        Ok(status) => Err(format!("client exited with {status}")),
+        Err(error) => Err(error),
+    }
+}
+
+/// The directory of the release binaries, after building them.
+fn build_release(implementations: &[Implementation]) -> Result<PathBuf, String> {
+    let workspace = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("utils is a member of the workspace");
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+
+    let mut build = Command::new(&cargo);
+    build.current_dir(workspace).args(["build", "--release"]);
+    for implementation in implementations {
+        build.args(["--bin", implementation.package()]);
+    }
+    let status = build
+        .status()
+        .map_err(|error| format!("failed to run {cargo}: {error}"))?;
+    if !status.success() {
+        return Err(format!(
+            "building the release binaries failed with {status}"
+        ));
+    }
+
+    let metadata = Command::new(&cargo)
+        .current_dir(workspace)
+        .args(["metadata", "--format-version", "1", "--no-deps"])
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|error| format!("failed to run {cargo}: {error}"))?;
+    let metadata: serde_json::Value = serde_json::from_slice(&metadata.stdout)
+        .map_err(|error| format!("invalid cargo metadata: {error}"))?;
+    let target = metadata["target_directory"]
+        .as_str()
+        .ok_or("cargo metadata without a target directory")?;
+    Ok(Path::new(target).join("release"))
+}
+
+/// The client arguments of one cell.
+fn client_args(
+    opts: &MatrixOpts,
+    transport: TransportLayer,
+    dial: &str,
+    size: ByteSize,
+) -> Vec<String> {
+    let size = size.0.to_string();
+    [
+        "client",
+        "--server-address",
+        dial,
+        "--transport-layer",
+        transport_name(transport),
+        "--upload-bytes",
+        &size,
+        "--download-bytes",
+        &size,
+        "--iterations",
+        &opts.iterations.to_string(),
+        "--warmup",
+        &opts.warmup.to_string(),
+        "--output",
+        "json",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let opts = MatrixOpts::parse();
+    utils::set_units(opts.units);
+
+    let mut cells = Vec::new();
+    for &transport in &opts.transports {
+        for &server in &opts.implementations {
+            for &client in &opts.implementations {
+                if server.supports(transport) && client.supports(transport) {
+                    cells.push((transport, server, client));
+                } else {
+                    eprintln!(
+                        "Skipping {} -> {} over {}, unsupported transport",
+                        client.name(),
+                        server.name(),
+                        transport_name(transport)
+                    );
+                }
+            }
+        }
+    }
+    if cells.is_empty() {
+        return Err("no implementation pair supports the transports".into());
+    }
+
+    let binaries = match &opts.binaries {
+        Some(dir) => dir.clone(),
+        None => build_release(&opts.implementations)?,
+    };
+    let mut output: Box<dyn Write> = match &opts.output {
+        Some(path) => Box::new(std::fs::File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    };
+
+    let mut failed = 0;
+    let mut port = opts.port;
+    for transport in &opts.transports {
+        for server in &opts.implementations {
+            let clients: Vec<_> = cells
+                .iter()
+                .filter(|cell| cell.0 == *transport && cell.1 == *server)
+                .map(|cell| cell.2)
+                .collect();
+            if clients.is_empty() {
+                continue;
+            }
+
+            let address = listen_address(*transport, port)?;
+            port = port.wrapping_add(1);
+            eprintln!("Starting the {} server on {address}", server.name());
+            let (server_process, ready) = match Server::start(
+                &binaries.join(server.package()),
+                *server,
+                *transport,
+                &address,
+                Duration::from_secs(opts.ready_timeout),
+            ) {
+                Ok(server_process) => server_process,
+                Err(error) => {
+                    eprintln!("{error}");
+                    failed += clients.len() * opts.sizes.len();
+                    continue;
+                }
+            };
+            // The server listens on a single interface.
+            let dial = ready.listen_addresses[0].clone();
+
+            for client in clients {
+                for &size in &opts.sizes {
+                    let label = format!(
+                        "{} -> {} over {}, {size}",
+                        client.name(),
+                        server.name(),
+                        transport_name(*transport)
+                    );
+                    let args = client_args(&opts, *transport, &dial, size);
+                    let results = run_client(
+                        &binaries.join(client.package()),
+                        &args,
+                        Duration::from_secs(opts.cell_timeout),
+                    )
+                    .and_then(|stdout| {
+                        stdout
+                            .lines()
+                            .filter(|line| line.starts_with('{'))
+                            .map(|line| {
+                                serde_json::from_str::<PerfResult>(line)
+                                    .map_err(|error| format!("invalid client result: {error}"))
+                            })
+                            .collect::<Result<Vec<_>, _>>()
+                    });
+                    let results = match results {
+                        Ok(results) => results,
+                        Err(error) => {
+                            // Record the cell as failed, as the client does in JSON mode.
+                            let client_opts = ClientOpts::try_parse_from(&args)?;
+                            vec![client_opts.failed(client.name(), &error)]
+                        }
+                    };
+
+                    for mut result in results {
+                        if let Some(error) = &result.error {
+                            eprintln!("{label}: failed, {error}");
+                            failed += 1;
+                        } else if !result.warmup {
+                            eprintln!(
+                                "{label}: uploaded {}, downloaded {}",
+                                utils::Bandwidth::from_bits_per_second(
+                                    result.upload.throughput() * 8.0
+                                ),
+                                utils::Bandwidth::from_bits_per_second(
+                                    result.download.throughput() * 8.0
+                                ),
+                            );
+                        }
+                        // The matrix knows the server even when it did not report itself.
+                        result
+                            .server_implementation
+                            .get_or_insert_with(|| server.name().to_string());
+                        writeln!(output, "{}", serde_json::to_string(&result)?)?;
+                    }
+                    output.flush()?;
+                }
+            }
+            drop(server_process);
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{failed} cell(s) failed").into());
+    }
+    Ok(())
+}
@@ -0,0 +1,55 @@
+//! Generate the README bandwidth tables, summary figures and charts from JSON perf results.
+
+use std::path::PathBuf;
+
+use clap::Parser as ClapParser;
+
+use utils::{BandwidthReport, Metric, PerfResult, UnitSystem};
+
+/// Generate the markdown tables, the summary figures and the SVG charts of the bandwidth of
+/// each implementation pair from the results printed by clients with `--output json`.
+#[derive(Debug, ClapParser)]
+struct ReportOpts {
+    /// Files of results, one JSON object per line.
+    #[clap(required = true)]
+    results: Vec<PathBuf>,
+
+    /// Write the upload and download charts of each transport to this directory and link them
+    /// from the markdown.
+    #[clap(long)]
+    charts: Option<PathBuf>,
+
+    /// The prefixes used to print sizes and bandwidths.
+    #[clap(long, default_value = "si")]
+    units: UnitSystem,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let opts = ReportOpts::parse();
+
+    let mut results = Vec::new();
+    for path in &opts.results {
+        results.extend(PerfResult::load(path)?);
+    }
+
+    let reports = BandwidthReport::new(&results);
+    if reports.is_empty() {
+        return Err(
+            "no fixed size or time bounded run over a single substream in the results".into(),
+        );
+    }
+    for report in &reports {
+        if let Some(dir) = opts.charts.as_ref().filter(|_| !report.sizes.is_empty()) {
+            std::fs::create_dir_all(dir)?;
+            for metric in [Metric::Upload, Metric::Download] {
+                std::fs::write(
+                    dir.join(report.chart_name(metric)),
+                    report.chart(metric, opts.units),
+                )?;
+            }
+        }
+        let chart_dir = opts.charts.as_ref().map(|dir| dir.display().to_string());
+        println!("{}", report.markdown(opts.units, chart_dir.as_deref()));
+    }
+    Ok(())
+}
@@ -0,0 +1,348 @@
+use std::{collections::BTreeMap, fmt, time::Duration};
+
+use crate::{Bandwidth, Case, PerfResult};
+
+/// What is compared between two sets of results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Metric {
+    /// Upload throughput, in bytes per second.
+    Upload,
+    /// Download throughput, in bytes per second.
+    Download,
+    /// Median round trip time of a latency run, in seconds.
+    Latency,
+}
+
+impl Metric {
+    /// The metrics measured by the runs of `case`.
+    fn of(case: &Case) -> Vec<Metric> {
+        if case.mode == "latency" {
+            return vec![Metric::Latency];
+        }
+        [
+            (Metric::Upload, case.upload),
+            (Metric::Download, case.download),
+        ]
+        .into_iter()
+        .filter(|(_, requested)| !requested.is_empty())
+        .map(|(metric, _)| metric)
+        .collect()
+    }
+
+    fn value(self, result: &PerfResult) -> Option<f64> {
+        match self {
+            Metric::Upload => Some(result.upload.throughput()),
+            Metric::Download => Some(result.download.throughput()),
+            Metric::Latency => result.latency.map(|latency| latency.p50_seconds),
+        }
+    }
+
+    fn higher_is_better(self) -> bool {
+        !matches!(self, Metric::Latency)
+    }
+
+    /// Format a value of the metric.
+    pub fn format(self, value: f64) -> String {
+        match self {
+            Metric::Upload | Metric::Download => {
+                Bandwidth::from_bits_per_second(value * 8.0).to_string()
+            }
+            Metric::Latency => format!("{:?}", Duration::from_secs_f64(value)),
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Metric::Upload => "upload",
+            Metric::Download => "download",
+            Metric::Latency => "latency p50",
+        })
+    }
+}
+
+/// Outcome of the comparison of one metric of one case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Significantly worse, by more than the threshold.
+    Regression,
+    /// Significantly better, by more than the threshold.
+    Improvement,
+    /// Not significant, or within the threshold.
+    Unchanged,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Verdict::Regression => "regression",
+            Verdict::Improvement => "improvement",
+            Verdict::Unchanged => "unchanged",
+        })
+    }
+}
+
+/// When a change counts as a regression or an improvement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Significance {
+    /// Largest p-value of the Mann-Whitney U test that counts as significant.
+    pub alpha: f64,
+    /// Smallest change of the median, in percent, that counts.
+    pub threshold: f64,
+}
+
+/// One metric of one case measured in both sets of results.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub case: Case,
+    pub metric: Metric,
+    pub baseline: Vec<f64>,
+    pub candidate: Vec<f64>,
+    /// Two-sided p-value of the Mann-Whitney U test.
+    pub p_value: f64,
+    /// Change of the median from the baseline to the candidate, in percent.
+    pub change: f64,
+    pub verdict: Verdict,
+}
+
+impl Comparison {
+    fn new(
+        case: Case,
+        metric: Metric,
+        baseline: Vec<f64>,
+        candidate: Vec<f64>,
+        significance: Significance,
+    ) -> Self {
+        let p_value = mann_whitney_u(&baseline, &candidate);
+        let (baseline_median, candidate_median) = (median(&baseline), median(&candidate));
+        let change =
+            (candidate_median - baseline_median) / baseline_median.abs().max(f64::EPSILON) * 100.0;
+        let better = if metric.higher_is_better() {
+            change
+        } else {
+            -change
+        };
+        let verdict = if p_value > significance.alpha || better.abs() < significance.threshold {
+            Verdict::Unchanged
+        } else if better < 0.0 {
+            Verdict::Regression
+        } else {
+            Verdict::Improvement
+        };
+
+        Self {
+            case,
+            metric,
+            baseline,
+            candidate,
+            p_value,
+            change,
+            verdict,
+        }
+    }
+
+    pub fn baseline_median(&self) -> f64 {
+        median(&self.baseline)
+    }
+
+    pub fn candidate_median(&self) -> f64 {
+        median(&self.candidate)
+    }
+}
+
+/// Cases measured in a single set of results.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Unmatched {
+    pub baseline: Vec<Case>,
+    pub candidate: Vec<Case>,
+}
+
+/// Line up the cases measured in both sets of results and compare each of their metrics.
+///
+/// Warmup iterations and failed runs are left out, every other run of a case is one sample.
+pub fn compare(
+    baseline: &[PerfResult],
+    candidate: &[PerfResult],
+    significance: Significance,
+) -> (Vec<Comparison>, Unmatched) {
+    let baseline = by_case(baseline);
+    let mut candidate = by_case(candidate);
+
+    let mut comparisons = Vec::new();
+    let mut unmatched = Unmatched::default();
+    for (case, baseline_runs) in baseline {
+        let Some(candidate_runs) = candidate.remove(&case) else {
+            unmatched.baseline.push(case);
+            continue;
+        };
+        for metric in Metric::of(&case) {
+            let samples = |runs: &[&PerfResult]| -> Vec<f64> {
+                runs.iter()
+                    .filter_map(|result| metric.value(result))
+                    .collect()
+            };
+            let (baseline_samples, candidate_samples) =
+                (samples(&baseline_runs), samples(&candidate_runs));
+            if baseline_samples.is_empty() || candidate_samples.is_empty() {
+                continue;
+            }
+            comparisons.push(Comparison::new(
+                case.clone(),
+                metric,
+                baseline_samples,
+                candidate_samples,
+                significance,
+            ));
+        }
+    }
+    unmatched.candidate = candidate.into_keys().collect();
+
+    (comparisons, unmatched)
+}
+
+fn by_case(results: &[PerfResult]) -> BTreeMap<Case, Vec<&PerfResult>> {
+    let mut cases: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for result in results
+        .iter()
+        .filter(|result| !result.warmup && result.error.is_none())
+    {
+        cases.entry(result.case()).or_default().push(result);
+    }
+    cases
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let n = sorted.len();
+    match n {
+        0 => 0.0,
+        _ if n.is_multiple_of(2) => (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0,
+        _ => sorted[n / 2],
+    }
+}
+
+/// Largest combined sample size for which the exact distribution of U is computed.
+const EXACT_MAX_SAMPLES: usize = 20;
+
+/// Two-sided p-value of the Mann-Whitney U test that both samples come from the same
+/// distribution.
+///
+/// The exact distribution of U is used for small samples without ties, the normal
+/// approximation with tie and continuity corrections otherwise.
+pub fn mann_whitney_u(a: &[f64], b: &[f64]) -> f64 {
+    let (n1, n2) = (a.len(), b.len());
+    if n1 == 0 || n2 == 0 {
+        return 1.0;
+    }
+
+    // Rank both samples together, ties get the average of their ranks.
+    let mut values: Vec<(f64, bool)> = a
+        .iter()
+        .map(|&value| (value, true))
+        .chain(b.iter().map(|&value| (value, false)))
+        .collect();
+    values.sort_by(|x, y| x.0.total_cmp(&y.0));
+    let n = values.len();
+    let mut rank_sum = 0.0;
+    let mut tie_correction = 0.0;
+    let mut start = 0;
+    while start < n {
+        let end = (start..n)
+            .find(|&index| values[index].0 != values[start].0)
+            .unwrap_or(n);
+        let rank = (start + end + 1) as f64 / 2.0;
+        rank_sum += rank * values[start..end].iter().filter(|value| value.1).count() as f64;
+        let ties = (end - start) as f64;
+        tie_correction += ties * ties * ties - ties;
+        start = end;
+    }
+
+    let u1 = rank_sum - (n1 * (n1 + 1)) as f64 / 2.0;
+    let u = u1.min((n1 * n2) as f64 - u1);
+    if tie_correction == 0.0 && n <= EXACT_MAX_SAMPLES {
+        return (2.0 * exact_cdf(n1, n2, u as usize)).min(1.0);
+    }
+
+    let mean = (n1 * n2) as f64 / 2.0;
+    let variance =
+        (n1 * n2) as f64 / 12.0 * ((n + 1) as f64 - tie_correction / (n * (n - 1)) as f64);
+    if variance <= 0.0 {
+        return 1.0;
+    }
+    let z = ((mean - u).abs() - 0.5).max(0.0) / variance.sqrt();
+    erfc(z / std::f64::consts::SQRT_2).min(1.0)
+}
+
+/// Probability that U is at most `u` for samples of `n1` and `n2` values without ties.
+fn exact_cdf(n1: usize, n2: usize, u: usize) -> f64 {
+    // counts[i][j][k]: orderings of i values of the first and j of the second sample with U = k,
+    // built up one value at a time.
+    let max = n1 * n2;
+    let mut counts = vec![vec![vec![0.0; max + 1]; n2 + 1]; n1 + 1];
+    for i in 0..=n1 {
+        for j in 0..=n2 {
+            if i == 0 || j == 0 {
+                counts[i][j][0] = 1.0;
+                continue;
+            }
+            for k in 0..=i * j {
+                // The largest value is either from the first sample, above the j values of the
+                // second one, or from the second sample.
+                let first = if k >= j { counts[i - 1][j][k - j] } else { 0.0 };
+                counts[i][j][k] = first + counts[i][j - 1][k];
+            }
+        }
+    }
+
+    let total: f64 = counts[n1][n2].iter().sum();
+    counts[n1][n2][..=u.min(max)].iter().sum::<f64>() / total
+}
+
+/// Complementary error function, with a relative error below 1.2e-7.
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let result = t
+        * (-z * z - 1.26551223
+            + t * (1.00002368
+                + t * (0.37409196
+                    + t * (0.09678418
+                        + t * (-0.18628806
+                            + t * (0.27886807
+                                + t * (-1.13520398
+                                    + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277)))))))))
+            .exp();
+    if x >= 0.0 { result } else { 2.0 - result }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_p_value_of_separated_samples() {
+        // All 5 values of one sample below the 5 of the other, 2 orderings out of 252 are as
+        // extreme.
+        let p = mann_whitney_u(&[1.0, 2.0, 3.0, 4.0, 5.0], &[6.0, 7.0, 8.0, 9.0, 10.0]);
+        assert!((p - 2.0 / 252.0).abs() < 1e-12, "{p}");
+    }
+
+    #[test]
+    fn identical_samples_are_not_significant() {
+        let p = mann_whitney_u(&[1.0, 1.0, 1.0], &[1.0, 1.0, 1.0]);
+        assert_eq!(p, 1.0);
+
+        let p = mann_whitney_u(&[1.0, 3.0, 5.0, 7.0], &[2.0, 4.0, 6.0, 8.0]);
+        assert!(p > 0.5, "{p}");
+    }
+
+    #[test]
+    fn normal_approximation_with_ties() {
+        let a: Vec<f64> = (0..15).map(|value| (value / 2) as f64).collect();
+        let b: Vec<f64> = (0..15).map(|value| (value / 2 + 10) as f64).collect();
+        let p = mann_whitney_u(&a, &b);
+        assert!(p < 1e-5, "{p}");
+    }
+}
@@ -0,0 +1,922 @@
+use std::{
+    io::IoSlice,
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, future};
+
+use crate::{
+    ByteClock, LatencyStats, PayloadGenerator, PayloadVerifier, PerfError, ProtocolVersion,
+    REQUEST_ACCEPTED, REQUEST_REJECTED, RequestHeader, RunParams, RunStats, SideReport, TimedMeter,
+    TimedTransfer, TransferOpts, UsageMeter, Workload, WriteStrategy,
+};
+
+const LOG_TARGET: &str = "litep2p-perf";
+
+/// Number of chunks handed to a single vectored write.
+const VECTORED_CHUNKS: usize = 16;
+
+/// Largest write of [`WriteStrategy::Single`], larger payloads are sent in writes of this size
+/// as the payload size comes from the remote.
+const MAX_SINGLE_WRITE: u64 = 64 * 1024 * 1024;
+
+/// Length of the frame header of a time bounded transfer.
+const FRAME_HEADER: usize = 4;
+
+async fn read_u64<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    substream: &mut S,
+) -> Result<u64, PerfError> {
+    let mut buf = [0u8; 8];
+    substream.read_exact(&mut buf).await?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+async fn write_u64<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    substream: &mut S,
+    value: u64,
+) -> Result<(), PerfError> {
+    substream.write_all(&value.to_be_bytes()).await?;
+    Ok(())
+}
+
+async fn recv_bytes<S: AsyncRead + Unpin>(
+    substream: &mut S,
+    to_recv: u64,
+    seed: Option<u64>,
+    transfer: TransferOpts,
+    clock: &mut ByteClock,
+) -> Result<u64, PerfError> {
+    let mut buf = vec![0u8; transfer.chunk_size];
+    let mut verifier = seed.map(PayloadVerifier::new);
+    let mut total = 0;
+    while total < to_recv {
+        // Never read past the payload, the next message follows on the same substream.
+        let len = (to_recv - total).min(buf.len() as u64) as usize;
+        let n = substream.read(&mut buf[..len]).await?;
+        if n == 0 {
+            return Err(PerfError::ShortTransfer {
+                expected: to_recv,
+                received: total,
+            });
+        }
+        if let Some(verifier) = verifier.as_mut() {
+            verifier.verify(&buf[..n])?;
+        }
+        total += n as u64;
+        clock.record(n);
+    }
+    clock.finish();
+    Ok(total)
+}
+
+async fn recv_to_end<S: AsyncRead + Unpin>(
+    substream: &mut S,
+    transfer: TransferOpts,
+    clock: &mut ByteClock,
+) -> Result<u64, PerfError> {
+    let mut buf = vec![0u8; transfer.chunk_size];
+    let mut total = 0;
+    loop {
+        let n = substream.read(&mut buf).await?;
+        if n == 0 {
+            clock.finish();
+            return Ok(total);
+        }
+        total += n as u64;
+        clock.record(n);
+    }
+}
+
+async fn write_all_vectored<S: AsyncWrite + Unpin>(
+    substream: &mut S,
+    mut slices: &mut [IoSlice<'_>],
+) -> Result<(), PerfError> {
+    while !slices.is_empty() {
+        let n = substream.write_vectored(slices).await?;
+        if n == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into());
+        }
+        IoSlice::advance_slices(&mut slices, n);
+    }
+    Ok(())
+}
+
+async fn send_bytes<S: AsyncWrite + Unpin>(
+    substream: &mut S,
+    to_send: u64,
+    seed: Option<u64>,
+    transfer: TransferOpts,
+    clock: &mut ByteClock,
+) -> Result<u64, PerfError> {
+    let mut generator = seed.map(PayloadGenerator::new);
+    let mut fill = |buf: &mut [u8]| {
+        if let Some(generator) = generator.as_mut() {
+            generator.fill(buf);
+        }
+    };
+
+    let mut total = 0;
+    match transfer.write_strategy {
+        WriteStrategy::WriteAll => {
+            let mut buf = vec![0u8; transfer.chunk_size];
+            while total < to_send {
+                let len = (to_send - total).min(buf.len() as u64) as usize;
+                fill(&mut buf[..len]);
+                substream.write_all(&buf[..len]).await?;
+                total += len as u64;
+                clock.record(len);
+            }
+        }
+        WriteStrategy::Vectored => {
+            let mut buf = vec![0u8; transfer.chunk_size * VECTORED_CHUNKS];
+            while total < to_send {
+                let len = (to_send - total).min(buf.len() as u64) as usize;
+                fill(&mut buf[..len]);
+                let mut slices: Vec<_> = buf[..len]
+                    .chunks(transfer.chunk_size)
+                    .map(IoSlice::new)
+                    .collect();
+                write_all_vectored(substream, &mut slices).await?;
+                total += len as u64;
+                clock.record(len);
+            }
+        }
+        WriteStrategy::Single => {
+            let mut buf = vec![0u8; to_send.min(MAX_SINGLE_WRITE) as usize];
+            while total < to_send {
+                let len = (to_send - total).min(buf.len() as u64) as usize;
+                fill(&mut buf[..len]);
+                substream.write_all(&buf[..len]).await?;
+                substream.flush().await?;
+                total += len as u64;
+                clock.record(len);
+            }
+        }
+    }
+    clock.finish();
+    Ok(total)
+}
+
+async fn send_timed<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    substream: &mut S,
+    seed: Option<u64>,
+    transfer: TransferOpts,
+    clock: &mut ByteClock,
+) -> Result<TimedTransfer, PerfError> {
+    let (mut reader, mut writer) = substream.split();
+    let mut generator = seed.map(PayloadGenerator::new);
+    let mut buf = vec![0u8; FRAME_HEADER + transfer.chunk_size];
+    buf[..FRAME_HEADER].copy_from_slice(&(transfer.chunk_size as u32).to_be_bytes());
+
+    let mut stop = [0u8; TimedTransfer::ENCODED_LEN];
+    let mut total = 0;
+    {
+        // Keep writing frames until the receiver sends the stop message.
+        let mut stop_read = std::pin::pin!(reader.read_exact(&mut stop));
+        loop {
+            if let Poll::Ready(result) = futures::poll!(stop_read.as_mut()) {
+                result?;
+                break;
+            }
+            if let Some(generator) = generator.as_mut() {
+                generator.fill(&mut buf[FRAME_HEADER..]);
+            }
+            writer.write_all(&buf).await?;
+            total += transfer.chunk_size as u64;
+            clock.record(transfer.chunk_size);
+        }
+    }
+
+    clock.finish();
+
+    writer.write_all(&0u32.to_be_bytes()).await?;
+    writer.flush().await?;
+    Ok(TimedTransfer::decode(&stop, total))
+}
+
+async fn recv_timed<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    substream: &mut S,
+    duration: Duration,
+    seed: Option<u64>,
+    transfer: TransferOpts,
+    clock: &mut ByteClock,
+) -> Result<TimedTransfer, PerfError> {
+    let mut buf = vec![0u8; transfer.chunk_size];
+    let mut verifier = seed.map(PayloadVerifier::new);
+    let mut meter = TimedMeter::new(duration);
+    let mut header = [0u8; FRAME_HEADER];
+    loop {
+        substream.read_exact(&mut header).await?;
+        let len = u32::from_be_bytes(header) as usize;
+        if len == 0 {
+            break;
+        }
+        // Frames are at most one chunk, the length is not trusted with an allocation.
+        if len > buf.len() {
+            return Err(PerfError::Protocol("frame larger than the chunk size"));
+        }
+        substream.read_exact(&mut buf[..len]).await?;
+        if let Some(verifier) = verifier.as_mut() {
+            verifier.verify(&buf[..len])?;
+        }
+        clock.record(len);
+        if let Some(timed) = meter.record(len) {
+            substream.write_all(&timed.encode()).await?;
+            substream.flush().await?;
+        }
+    }
+
+    clock.finish();
+
+    meter
+        .finish()
+        .ok_or(PerfError::Protocol("sender stopped before the deadline"))
+}
+
+async fn echo_messages<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    substream: &mut S,
+    messages: u64,
+    size: u64,
+    seed: Option<u64>,
+    upload_clock: &mut ByteClock,
+    download_clock: &mut ByteClock,
+) -> Result<(), PerfError> {
+    let mut buf = vec![0u8; size as usize];
+    let mut verifier = seed.map(PayloadVerifier::new);
+    for _ in 0..messages {
+        substream.read_exact(&mut buf).await?;
+        upload_clock.record(buf.len());
+        if let Some(verifier) = verifier.as_mut() {
+            verifier.verify(&buf)?;
+        }
+        substream.write_all(&buf).await?;
+        substream.flush().await?;
+        download_clock.record(buf.len());
+    }
+    Ok(())
+}
+
+async fn client_latency<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    substream: &mut S,
+    messages: u64,
+    size: u64,
+    seed: Option<u64>,
+    upload_clock: &mut ByteClock,
+    download_clock: &mut ByteClock,
+) -> Result<LatencyStats, PerfError> {
+    let mut generator = seed.map(PayloadGenerator::new);
+    let mut message = vec![0u8; size as usize];
+    let mut echo = vec![0u8; size as usize];
+    let mut rtts = Vec::with_capacity(messages as usize);
+    for index in 0..messages {
+        if let Some(generator) = generator.as_mut() {
+            generator.fill(&mut message);
+        }
+        let now = Instant::now();
+        substream.write_all(&message).await?;
+        substream.flush().await?;
+        upload_clock.record(message.len());
+        substream.read_exact(&mut echo).await?;
+        rtts.push(now.elapsed());
+        download_clock.record(echo.len());
+
+        if let Some(position) = message
+            .iter()
+            .zip(&echo)
+            .position(|(sent, echoed)| sent != echoed)
+        {
+            return Err(PerfError::PayloadMismatch {
+                offset: index * size + position as u64,
+            });
+        }
+    }
+    Ok(LatencyStats::new(rtts))
+}
+
+/// Serve one perf run on a negotiated substream.
+///
+/// `implementation` names this side in the report sent back to `/litep2p-perf/2.0.0` clients.
+pub async fn server_mode<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    substream: S,
+    version: ProtocolVersion,
+    verify: bool,
+    transfer: TransferOpts,
+    implementation: &'static str,
+) -> Result<(), PerfError> {
+    let usage = UsageMeter::start();
+    let bytes = match version {
+        ProtocolVersion::V1 => server_v1(substream, verify, transfer).await?,
+        ProtocolVersion::V2 => server_v2(substream, transfer, implementation).await?,
+        ProtocolVersion::Perf => server_perf(substream, transfer).await?,
+    };
+    crate::log_usage("Session", &usage.finish(), bytes);
+    Ok(())
+}
+
+async fn server_perf<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    mut substream: S,
+    transfer: TransferOpts,
+) -> Result<u64, PerfError> {
+    // Step 1. Read the download bytes.
+    let to_send = read_u64(&mut substream).await?;
+    // Step 2. Receive the upload until the client closes its write side.
+    let mut clock = ByteClock::new(Instant::now()).sampled(transfer.interval, "Upload");
+    recv_to_end(&mut substream, transfer, &mut clock).await?;
+    let received = clock.report().bytes;
+
+    // Step 3. Send the download bytes and close the substream.
+    let mut clock = ByteClock::new(Instant::now()).sampled(transfer.interval, "Download");
+    send_bytes(&mut substream, to_send, None, transfer, &mut clock).await?;
+    substream.close().await?;
+
+    Ok(received + clock.report().bytes)
+}
+
+async fn server_v1<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    mut substream: S,
+    verify: bool,
+    transfer: TransferOpts,
+) -> Result<u64, PerfError> {
+    let seed = verify.then_some(crate::DEFAULT_PAYLOAD_SEED);
+
+    // Step 1. Read the download bytes.
+    let to_recv = read_u64(&mut substream).await?;
+    // Step 2. Receive the download bytes.
+    let mut clock = ByteClock::new(Instant::now()).sampled(transfer.interval, "Upload");
+    recv_bytes(&mut substream, to_recv, seed, transfer, &mut clock).await?;
+    let received = clock.report().bytes;
+
+    // Step 3. Read the upload bytes.
+    let to_send = read_u64(&mut substream).await?;
+    // Step 4. Send the upload bytes.
+    let mut clock = ByteClock::new(Instant::now()).sampled(transfer.interval, "Download");
+    send_bytes(&mut substream, to_send, seed, transfer, &mut clock).await?;
+
+    Ok(received + clock.report().bytes)
+}
+
+async fn server_v2<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    mut substream: S,
+    transfer: TransferOpts,
+    implementation: &'static str,
+) -> Result<u64, PerfError> {
+    // Step 1. Read the request header and accept or reject it.
+    let mut buf = [0u8; RequestHeader::ENCODED_LEN];
+    substream.read_exact(&mut buf).await?;
+    let header = match RequestHeader::decode(&buf) {
+        Ok(header) => header,
+        Err(error) => {
+            substream.write_all(&[REQUEST_REJECTED]).await?;
+            substream.close().await?;
+            return Err(error);
+        }
+    };
+    substream.write_all(&[REQUEST_ACCEPTED]).await?;
+    substream.flush().await?;
+    tracing::debug!(target: LOG_TARGET, ?header, "Accepted request");
+
+    let seed = header.seed();
+    let transfer = TransferOpts {
+        chunk_size: header.chunk_size as usize,
+        ..transfer
+    };
+    let start = Instant::now();
+    let mut upload_clock = ByteClock::new(start).sampled(transfer.interval, "Upload");
+    let mut download_clock = ByteClock::new(start).sampled(transfer.interval, "Download");
+    match header.workload {
+        Workload::Bytes { upload, download } if header.duplex => {
+            // Step 2. Receive the upload and send the download at the same time.
+            let (mut reader, mut writer) = substream.split();
+            future::try_join(
+                recv_bytes(&mut reader, upload, seed, transfer, &mut upload_clock),
+                send_bytes(&mut writer, download, seed, transfer, &mut download_clock),
+            )
+            .await?;
+            substream = reader
+                .reunite(writer)
+                .expect("halves of the same substream");
+        }
+        Workload::Bytes { upload, download } => {
+            // Step 2. Receive the upload bytes and acknowledge them if requested.
+            let received =
+                recv_bytes(&mut substream, upload, seed, transfer, &mut upload_clock).await?;
+            if header.upload_ack {
+                write_u64(&mut substream, received).await?;
+                substream.flush().await?;
+            }
+            // Step 3. Send the download bytes.
+            send_bytes(
+                &mut substream,
+                download,
+                seed,
+                transfer,
+                &mut download_clock,
+            )
+            .await?;
+        }
+        Workload::Duration { upload, download } => {
+            // Step 2. Receive the upload until the deadline.
+            if !upload.is_zero() {
+                recv_timed(&mut substream, upload, seed, transfer, &mut upload_clock).await?;
+            }
+            // Step 3. Send the download until the client stops us.
+            if !download.is_zero() {
+                send_timed(&mut substream, seed, transfer, &mut download_clock).await?;
+            }
+        }
+        Workload::Latency { messages, size } => {
+            // Step 2. Echo each message back as soon as it is received.
+            echo_messages(
+                &mut substream,
+                messages,
+                size,
+                seed,
+                &mut upload_clock,
+                &mut download_clock,
+            )
+            .await?;
+        }
+    }
+
+    upload_clock.finish();
+    download_clock.finish();
+
+    // Step 4. Report the server view of the run.
+    if header.server_report {
+        let report = SideReport::new(implementation, &upload_clock, &download_clock);
+        substream.write_all(&report.encode()).await?;
+        substream.flush().await?;
+    }
+
+    Ok(upload_clock.report().bytes + download_clock.report().bytes)
+}
+
+/// Run `run` on a negotiated substream.
+///
+/// `implementation` names this side in the client report.
+pub async fn client_mode<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    substream: S,
+    version: ProtocolVersion,
+    request_id: u64,
+    run: RunParams,
+    implementation: &'static str,
+) -> Result<RunStats, PerfError> {
+    tracing::debug!(target: LOG_TARGET, ?version, request_id, "Starting run");
+    let mut stats = match version {
+        ProtocolVersion::V1 => client_v1(substream, run, implementation).await?,
+        ProtocolVersion::V2 => client_v2(substream, request_id, run, implementation).await?,
+        ProtocolVersion::Perf => client_perf(substream, run, implementation).await?,
+    };
+    stats.first_byte = stats
+        .client_report
+        .as_ref()
+        .and_then(SideReport::time_to_first_byte);
+    Ok(stats)
+}
+
+async fn client_perf<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    mut substream: S,
+    run: RunParams,
+    implementation: &'static str,
+) -> Result<RunStats, PerfError> {
+    let (upload_bytes, download_bytes) = match run.workload {
+        Workload::Bytes { upload, download } => (upload, download),
+        Workload::Duration { .. } => {
+            return Err(PerfError::Protocol(
+                "time bounded transfers require /litep2p-perf/2.0.0",
+            ));
+        }
+        Workload::Latency { .. } => {
+            return Err(PerfError::Protocol(
+                "latency runs require /litep2p-perf/2.0.0",
+            ));
+        }
+    };
+    if run.duplex {
+        return Err(PerfError::Protocol(
+            "duplex transfers require /litep2p-perf/2.0.0",
+        ));
+    }
+    if run.verify {
+        return Err(PerfError::Protocol(
+            "payload verification requires /litep2p-perf/2.0.0",
+        ));
+    }
+    let mut stats = RunStats::default();
+    let start = Instant::now();
+    let mut upload_clock = ByteClock::new(start).sampled(run.transfer.interval, "Upload");
+    let mut download_clock = ByteClock::new(start).sampled(run.transfer.interval, "Download");
+
+    // Step 1. Send the download bytes.
+    write_u64(&mut substream, download_bytes).await?;
+    // Step 2. Send the upload bytes and close the write side.
+    client_upload(
+        &mut substream,
+        &mut stats,
+        upload_bytes,
+        None,
+        run.transfer,
+        false,
+        &mut upload_clock,
+    )
+    .await?;
+    substream.close().await?;
+
+    // Step 3. Receive the download bytes, the server closes the substream after them.
+    (stats.downloaded, stats.download_time) = client_download(
+        &mut substream,
+        download_bytes,
+        None,
+        run.transfer,
+        &mut download_clock,
+    )
+    .await?;
+    if substream.read(&mut [0u8; 1]).await? != 0 {
+        return Err(PerfError::Protocol(
+            "server sent more than the requested bytes",
+        ));
+    }
+    stats.upload_intervals = upload_clock.intervals();
+    stats.download_intervals = download_clock.intervals();
+    stats.client_report = Some(SideReport::new(
+        implementation,
+        &upload_clock,
+        &download_clock,
+    ));
+
+    Ok(stats)
+}
+
+async fn client_v1<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    mut substream: S,
+    run: RunParams,
+    implementation: &'static str,
+) -> Result<RunStats, PerfError> {
+    let (upload_bytes, download_bytes) = match run.workload {
+        Workload::Bytes { upload, download } => (upload, download),
+        Workload::Duration { .. } => {
+            return Err(PerfError::Protocol(
+                "time bounded transfers require /litep2p-perf/2.0.0",
+            ));
+        }
+        Workload::Latency { .. } => {
+            return Err(PerfError::Protocol(
+                "latency runs require /litep2p-perf/2.0.0",
+            ));
+        }
+    };
+    if run.duplex {
+        return Err(PerfError::Protocol(
+            "duplex transfers require /litep2p-perf/2.0.0",
+        ));
+    }
+    let seed = run.verify.then_some(crate::DEFAULT_PAYLOAD_SEED);
+    let mut stats = RunStats::default();
+    let start = Instant::now();
+    let mut upload_clock = ByteClock::new(start).sampled(run.transfer.interval, "Upload");
+    let mut download_clock = ByteClock::new(start).sampled(run.transfer.interval, "Download");
+
+    // Step 1. Send the upload bytes.
+    write_u64(&mut substream, upload_bytes).await?;
+    // Step 2. Send the upload bytes.
+    client_upload(
+        &mut substream,
+        &mut stats,
+        upload_bytes,
+        seed,
+        run.transfer,
+        false,
+        &mut upload_clock,
+    )
+    .await?;
+    // Step 3. Send the download bytes.
+    write_u64(&mut substream, download_bytes).await?;
+    // Step 4. Receive the download bytes.
+    (stats.downloaded, stats.download_time) = client_download(
+        &mut substream,
+        download_bytes,
+        seed,
+        run.transfer,
+        &mut download_clock,
+    )
+    .await?;
+    stats.upload_intervals = upload_clock.intervals();
+    stats.download_intervals = download_clock.intervals();
+    stats.client_report = Some(SideReport::new(
+        implementation,
+        &upload_clock,
+        &download_clock,
+    ));
+
+    Ok(stats)
+}
+
+async fn client_v2<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    mut substream: S,
+    request_id: u64,
+    run: RunParams,
+    implementation: &'static str,
+) -> Result<RunStats, PerfError> {
+    let header = RequestHeader::new(request_id, &run);
+    let seed = header.seed();
+    let transfer = run.transfer;
+    let mut stats = RunStats::default();
+
+    // Step 1. Send the request header and wait for the server to accept it.
+    substream.write_all(&header.encode()).await?;
+    substream.flush().await?;
+    let mut status = [0u8; 1];
+    substream.read_exact(&mut status).await?;
+    if status[0] != REQUEST_ACCEPTED {
+        return Err(PerfError::Protocol("request rejected by the server"));
+    }
+    let start = Instant::now();
+    let mut upload_clock = ByteClock::new(start).sampled(transfer.interval, "Upload");
+    let mut download_clock = ByteClock::new(start).sampled(transfer.interval, "Download");
+
+    match header.workload {
+        Workload::Bytes { upload, download } if header.duplex => {
+            // Step 2. Send the upload and receive the download at the same time.
+            let (mut reader, mut writer) = substream.split();
+            let now = Instant::now();
+            let upload_side = async {
+                let uploaded =
+                    send_bytes(&mut writer, upload, seed, transfer, &mut upload_clock).await?;
+                writer.flush().await?;
+                Ok::<_, PerfError>((uploaded, now.elapsed()))
+            };
+            let download_side = async {
+                let downloaded =
+                    recv_bytes(&mut reader, download, seed, transfer, &mut download_clock).await?;
+                Ok::<_, PerfError>((downloaded, now.elapsed()))
+            };
+            (
+                (stats.uploaded, stats.upload_time),
+                (stats.downloaded, stats.download_time),
+            ) = future::try_join(upload_side, download_side).await?;
+            let duplex_time = now.elapsed();
+            stats.duplex_time = Some(duplex_time);
+            crate::log_duplex(&stats, duplex_time);
+            substream = reader
+                .reunite(writer)
+                .expect("halves of the same substream");
+        }
+        Workload::Bytes { upload, download } => {
+            // Step 2. Send the upload bytes.
+            client_upload(
+                &mut substream,
+                &mut stats,
+                upload,
+                seed,
+                transfer,
+                header.upload_ack,
+                &mut upload_clock,
+            )
+            .await?;
+            // Step 3. Receive the download bytes.
+            (stats.downloaded, stats.download_time) = client_download(
+                &mut substream,
+                download,
+                seed,
+                transfer,
+                &mut download_clock,
+            )
+            .await?;
+        }
+        Workload::Duration { upload, download } => {
+            // Step 2. Upload until the server stops us.
+            if !upload.is_zero() {
+                let now = Instant::now();
+                let timed = send_timed(&mut substream, seed, transfer, &mut upload_clock).await?;
+                stats.upload_time = now.elapsed();
+                stats.uploaded = timed.bytes;
+                stats.timed_upload = Some(timed);
+                crate::log_timed("Uploaded", stats.upload_time, &timed);
+            }
+
+            // Step 3. Download until the deadline.
+            if !download.is_zero() {
+                let now = Instant::now();
+                let timed = recv_timed(
+                    &mut substream,
+                    download,
+                    seed,
+                    transfer,
+                    &mut download_clock,
+                )
+                .await?;
+                stats.download_time = now.elapsed();
+                stats.downloaded = timed.bytes;
+                stats.timed_download = Some(timed);
+                crate::log_timed("Downloaded", stats.download_time, &timed);
+            }
+        }
+        Workload::Latency { messages, size } => {
+            // Step 2. Send each message and wait for its echo.
+            let now = Instant::now();
+            let latency = client_latency(
+                &mut substream,
+                messages,
+                size,
+                seed,
+                &mut upload_clock,
+                &mut download_clock,
+            )
+            .await?;
+            stats.uploaded = messages * size;
+            stats.downloaded = messages * size;
+            stats.upload_time = now.elapsed();
+            stats.download_time = stats.upload_time;
+            crate::log_latency(&latency);
+            stats.latency = Some(latency);
+        }
+    }
+
+    upload_clock.finish();
+    download_clock.finish();
+    stats.upload_intervals = upload_clock.intervals();
+    stats.download_intervals = download_clock.intervals();
+
+    // Step 4. Read the server view of the run.
+    let client_report = SideReport::new(implementation, &upload_clock, &download_clock);
+    if header.server_report {
+        let mut buf = [0u8; SideReport::ENCODED_LEN];
+        substream.read_exact(&mut buf).await?;
+        let server_report = SideReport::decode(&buf);
+        crate::log_side_by_side(&client_report, &server_report);
+        stats.server_report = Some(server_report);
+    }
+    stats.client_report = Some(client_report);
+
+    Ok(stats)
+}
+
+async fn client_upload<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    substream: &mut S,
+    stats: &mut RunStats,
+    upload_bytes: u64,
+    seed: Option<u64>,
+    transfer: TransferOpts,
+    ack: bool,
+    clock: &mut ByteClock,
+) -> Result<(), PerfError> {
+    let now = Instant::now();
+    let uploaded = send_bytes(substream, upload_bytes, seed, transfer, clock).await?;
+    let send_time = now.elapsed();
+    stats.uploaded = uploaded;
+    if !ack {
+        stats.upload_time = send_time;
+        tracing::info!(
+            target: LOG_TARGET,
+            "Uploaded {} bytes in {:.4}s bandwidth {}",
+            crate::ByteSize(uploaded),
+            send_time.as_secs_f64(),
+            crate::Bandwidth::new(uploaded, send_time)
+        );
+        return Ok(());
+    }
+
+    // The upload ends when the server confirms it received every byte.
+    substream.flush().await?;
+    let received = read_u64(substream).await?;
+    if received != uploaded {
+        return Err(PerfError::ShortTransfer {
+            expected: uploaded,
+            received,
+        });
+    }
+    let upload_time = now.elapsed();
+    stats.upload_time = upload_time;
+    stats.upload_send_time = Some(send_time);
+    tracing::info!(
+        target: LOG_TARGET,
+        "Uploaded {} bytes in {:.4}s bandwidth {}, send-side {:.4}s bandwidth {}",
+        crate::ByteSize(uploaded),
+        upload_time.as_secs_f64(),
+        crate::Bandwidth::new(uploaded, upload_time),
+        send_time.as_secs_f64(),
+        crate::Bandwidth::new(uploaded, send_time)
+    );
+    Ok(())
+}
+
+async fn client_download<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    substream: &mut S,
+    download_bytes: u64,
+    seed: Option<u64>,
+    transfer: TransferOpts,
+    clock: &mut ByteClock,
+) -> Result<(u64, Duration), PerfError> {
+    let now = Instant::now();
+    let downloaded = recv_bytes(substream, download_bytes, seed, transfer, clock).await?;
+    let download_time = now.elapsed();
+    tracing::info!(
+        target: LOG_TARGET,
+        "Downloaded {} bytes in {:.4}s bandwidth {}",
+        crate::ByteSize(downloaded),
+        download_time.as_secs_f64(),
+        crate::Bandwidth::new(downloaded, download_time)
+    );
+    Ok((downloaded, download_time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio_util::compat::TokioAsyncReadCompatExt;
+
+    use crate::PerfProtocol;
+
+    const UPLOAD: u64 = 1024 * 1024 + 3;
+    const DOWNLOAD: u64 = 2 * 1024 * 1024 + 5;
+
+    fn run(workload: Workload) -> RunParams {
+        RunParams {
+            workload,
+            verify: true,
+            transfer: TransferOpts::default(),
+            duplex: false,
+            parallel: 1,
+            protocol: PerfProtocol::Litep2pPerf,
+        }
+    }
+
+    /// Run `run` between a client and a server connected by an in-memory pipe.
+    async fn run_v2(run: RunParams) -> (Result<RunStats, PerfError>, Result<(), PerfError>) {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let server = server_mode(
+            server.compat(),
+            ProtocolVersion::V2,
+            false,
+            TransferOpts::default(),
+            "server",
+        );
+        let client = client_mode(client.compat(), ProtocolVersion::V2, 7, run, "client");
+        tokio::join!(client, server)
+    }
+
+    #[tokio::test]
+    async fn bytes_round_trip() {
+        let (client, server) = run_v2(run(Workload::Bytes {
+            upload: UPLOAD,
+            download: DOWNLOAD,
+        }))
+        .await;
+        server.unwrap();
+        let stats = client.unwrap();
+
+        assert_eq!(stats.uploaded, UPLOAD);
+        assert_eq!(stats.downloaded, DOWNLOAD);
+        let server_report = stats.server_report.unwrap();
+        assert_eq!(server_report.upload.bytes, UPLOAD);
+        assert_eq!(server_report.download.bytes, DOWNLOAD);
+    }
+
+    #[tokio::test]
+    async fn rejects_frames_larger_than_the_chunk_size() {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let server = server_mode(
+            server.compat(),
+            ProtocolVersion::V2,
+            false,
+            TransferOpts::default(),
+            "server",
+        );
+        let client = async {
+            let mut client = client.compat();
+            let mut header = RequestHeader::new(
+                7,
+                &run(Workload::Duration {
+                    upload: Duration::from_secs(1),
+                    download: Duration::ZERO,
+                }),
+            );
+            header.verify = false;
+            client.write_all(&header.encode()).await.unwrap();
+            let mut status = [0u8; 1];
+            client.read_exact(&mut status).await.unwrap();
+            assert_eq!(status[0], REQUEST_ACCEPTED);
+            client.write_all(&u32::MAX.to_be_bytes()).await.unwrap();
+            client
+        };
+        let (_client, server) = tokio::join!(client, server);
+
+        assert!(matches!(server, Err(PerfError::Protocol(_))), "{server:?}");
+    }
+
+    #[tokio::test]
+    async fn latency_round_trip() {
+        let (client, server) = run_v2(run(Workload::Latency {
+            messages: 10,
+            size: 100,
+        }))
+        .await;
+        server.unwrap();
+        let stats = client.unwrap();
+
+        assert_eq!(stats.latency.unwrap().rtts.len(), 10);
+        assert_eq!(stats.uploaded, 1000);
+    }
+}
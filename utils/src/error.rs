@@ -0,0 +1,31 @@
+/// Errors reported by a perf run.
+#[derive(Debug, thiserror::Error)]
+pub enum PerfError {
+    /// The underlying substream failed.
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The connection to the server could not be established.
+    #[error("failed to connect: {0}")]
+    Dial(String),
+
+    /// The substream could not be opened.
+    #[error("failed to open substream: {0}")]
+    Substream(String),
+
+    /// The task running the perf protocol panicked or was cancelled.
+    #[error("perf task stopped: {0}")]
+    Task(String),
+
+    /// The remote did not follow the perf protocol.
+    #[error("protocol violation: {0}")]
+    Protocol(&'static str),
+
+    /// The received payload differs from the expected seeded stream.
+    #[error("payload mismatch at offset {offset}")]
+    PayloadMismatch { offset: u64 },
+
+    /// The remote closed the substream before the full payload was transferred.
+    #[error("short transfer: expected {expected} bytes, received {received}")]
+    ShortTransfer { expected: u64, received: u64 },
+}
@@ -0,0 +1,184 @@
+use std::time::Duration;
+
+/// Bits of the linear sub-buckets of each power of two range, values are kept with a relative
+/// error below `2 / 2^SUB_BUCKET_BITS`.
+const SUB_BUCKET_BITS: u32 = 7;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+const HALF_SUB_BUCKETS: u64 = SUB_BUCKETS / 2;
+
+/// Latency histogram with a bounded relative error, in the spirit of HdrHistogram.
+///
+/// Values are recorded in nanoseconds. Values below 128ns are kept exactly, each power of two
+/// range above is split into 64 linear buckets so that percentiles are within 1.6% of the
+/// recorded values whatever their magnitude.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    len: u64,
+    sum: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, value: Duration) {
+        let index = bucket(value.as_nanos().min(u64::MAX as u128) as u64);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+
+        self.min = if self.len == 0 {
+            value
+        } else {
+            self.min.min(value)
+        };
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.len += 1;
+    }
+
+    /// Number of recorded values.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn min(&self) -> Duration {
+        self.min
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.len == 0 {
+            return Duration::ZERO;
+        }
+        self.sum.div_f64(self.len as f64)
+    }
+
+    /// Nearest-rank percentile of the recorded values, `percent` is in `0..=100`.
+    ///
+    /// The highest value of the bucket holding the rank is reported, bounded by the recorded
+    /// minimum and maximum.
+    pub fn percentile(&self, percent: f64) -> Duration {
+        if self.len == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((percent / 100.0 * self.len as f64).ceil() as u64).clamp(1, self.len);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_nanos(highest_in_bucket(index)).clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+}
+
+/// Index of the bucket holding `value`.
+fn bucket(value: u64) -> usize {
+    if value < SUB_BUCKETS {
+        return value as usize;
+    }
+    // Shift the value into `HALF_SUB_BUCKETS..SUB_BUCKETS`.
+    let shift = u64::BITS - value.leading_zeros() - SUB_BUCKET_BITS;
+    let sub_bucket = (value >> shift) - HALF_SUB_BUCKETS;
+    (SUB_BUCKETS + (shift as u64 - 1) * HALF_SUB_BUCKETS + sub_bucket) as usize
+}
+
+/// Highest value that falls into the bucket at `index`.
+fn highest_in_bucket(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return index;
+    }
+    let shift = (index - SUB_BUCKETS) / HALF_SUB_BUCKETS + 1;
+    let sub_bucket = (index - SUB_BUCKETS) % HALF_SUB_BUCKETS + HALF_SUB_BUCKETS;
+    // The top of the last buckets does not fit in a u64.
+    (((sub_bucket + 1) as u128) << shift)
+        .saturating_sub(1)
+        .min(u64::MAX as u128) as u64
+}
+
+/// Log the distribution of the recorded values as `label`.
+pub fn log_histogram(label: &str, histogram: &LatencyHistogram) {
+    tracing::info!(
+        "{label} n={} min {:?} mean {:?} p50 {:?} p90 {:?} p99 {:?} p99.9 {:?} max {:?}",
+        histogram.len(),
+        histogram.min(),
+        histogram.mean(),
+        histogram.percentile(50.0),
+        histogram.percentile(90.0),
+        histogram.percentile(99.0),
+        histogram.percentile(99.9),
+        histogram.max(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_bound_the_relative_error() {
+        assert_eq!(bucket(127), 127);
+        assert_eq!(bucket(128), bucket(129));
+        assert_ne!(bucket(129), bucket(130));
+        assert_eq!(highest_in_bucket(bucket(255)), 255);
+        assert_eq!(bucket(256), bucket(255) + 1);
+
+        for value in (0..64).flat_map(|shift| [1u64 << shift, (1 << shift) + 1, (3 << shift) / 2]) {
+            let highest = highest_in_bucket(bucket(value));
+            assert_eq!(bucket(highest), bucket(value), "{value}");
+            assert!(highest >= value, "{value}");
+            assert!(
+                (highest - value) as f64 <= value as f64 * 2.0 / SUB_BUCKETS as f64,
+                "{value}"
+            );
+        }
+        assert_eq!(highest_in_bucket(bucket(u64::MAX)), u64::MAX);
+    }
+
+    #[test]
+    fn percentiles_of_exact_values() {
+        let mut histogram = LatencyHistogram::new();
+        for nanos in 1..=100 {
+            histogram.record(Duration::from_nanos(nanos));
+        }
+
+        assert_eq!(histogram.len(), 100);
+        assert_eq!(histogram.percentile(0.0), Duration::from_nanos(1));
+        assert_eq!(histogram.percentile(50.0), Duration::from_nanos(50));
+        assert_eq!(histogram.percentile(99.5), Duration::from_nanos(100));
+        assert_eq!(histogram.percentile(100.0), Duration::from_nanos(100));
+    }
+
+    #[test]
+    fn percentiles_report_the_top_of_the_bucket_within_the_recorded_range() {
+        let mut histogram = LatencyHistogram::new();
+        for nanos in [128, 129, 130] {
+            histogram.record(Duration::from_nanos(nanos));
+        }
+        // 128 and 129 share a bucket, whose top is 129.
+        assert_eq!(histogram.percentile(33.0), Duration::from_nanos(129));
+        assert_eq!(histogram.percentile(66.0), Duration::from_nanos(129));
+        // The top of the bucket of 130 is 131, above the maximum.
+        assert_eq!(histogram.percentile(100.0), Duration::from_nanos(130));
+
+        let mut histogram = LatencyHistogram::new();
+        histogram.record(Duration::from_micros(1000));
+        assert_eq!(histogram.percentile(50.0), Duration::from_micros(1000));
+        assert_eq!(LatencyHistogram::new().percentile(50.0), Duration::ZERO);
+    }
+}
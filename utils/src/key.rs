@@ -0,0 +1,245 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Length of an ed25519 secret key seed.
+pub const SEED_LEN: usize = 32;
+
+/// Length of an ed25519 keypair, the seed followed by the public key.
+const KEYPAIR_LEN: usize = 2 * SEED_LEN;
+
+/// `KeyType` of ed25519 keys in the libp2p `PrivateKey` protobuf message.
+const KEY_TYPE_ED25519: u64 = 1;
+
+/// Errors loading or storing the identity of a node.
+#[derive(Debug, thiserror::Error)]
+pub enum KeyError {
+    /// Passphrases are zero-padded to a seed, longer ones would be cut.
+    #[error("node key is {0} bytes long, at most 32 are supported, use --key-file instead")]
+    PassphraseTooLong(usize),
+
+    /// The key file could not be read or written.
+    #[error("{path}: {error}")]
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+
+    /// The key file is neither a raw seed nor a protobuf encoded ed25519 keypair.
+    #[error("{path}: not a raw 32-byte seed or a protobuf encoded ed25519 keypair, {reason}")]
+    InvalidKeyFile { path: PathBuf, reason: String },
+
+    /// The public key stored in the key file does not belong to its secret key.
+    #[error("the public key of the key file does not match its secret key")]
+    PublicKeyMismatch,
+}
+
+/// Where the identity of a node comes from.
+#[derive(Debug, Clone, clap::Args)]
+pub struct KeyOpts {
+    /// A passphrase of at most 32 bytes, zero-padded to the ed25519 secret key seed.
+    ///
+    /// Kept for existing setups, prefer `--key-file`.
+    #[clap(
+        long,
+        short,
+        required_unless_present = "key_file",
+        conflicts_with = "key_file"
+    )]
+    pub node_key: Option<String>,
+
+    /// A file holding a protobuf encoded libp2p ed25519 keypair, as written by `generate-key`,
+    /// or a raw 32-byte ed25519 secret key seed.
+    #[clap(long)]
+    pub key_file: Option<PathBuf>,
+}
+
+impl KeyOpts {
+    /// Load the key of the node.
+    pub fn load(&self) -> Result<NodeKey, KeyError> {
+        match (&self.node_key, &self.key_file) {
+            (_, Some(path)) => NodeKey::read(path),
+            (Some(passphrase), None) => NodeKey::from_passphrase(passphrase),
+            (None, None) => unreachable!("clap requires a node key or a key file"),
+        }
+    }
+}
+
+/// The ed25519 secret key seed a node derives its keypair and peer ID from.
+///
+/// Both implementations derive the same keypair, and so the same peer ID, from the same seed.
+#[derive(Clone, PartialEq, Eq)]
+pub struct NodeKey {
+    seed: [u8; SEED_LEN],
+    /// The public key stored next to the seed, checked against the derived one.
+    public: Option<[u8; SEED_LEN]>,
+}
+
+impl std::fmt::Debug for NodeKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NodeKey").finish_non_exhaustive()
+    }
+}
+
+impl NodeKey {
+    /// The key of a passphrase, its UTF-8 bytes zero-padded to a seed.
+    pub fn from_passphrase(passphrase: &str) -> Result<Self, KeyError> {
+        let bytes = passphrase.as_bytes();
+        if bytes.len() > SEED_LEN {
+            return Err(KeyError::PassphraseTooLong(bytes.len()));
+        }
+        let mut seed = [0; SEED_LEN];
+        seed[..bytes.len()].copy_from_slice(bytes);
+        Ok(Self { seed, public: None })
+    }
+
+    pub fn from_seed(seed: [u8; SEED_LEN]) -> Self {
+        Self { seed, public: None }
+    }
+
+    pub fn seed(&self) -> [u8; SEED_LEN] {
+        self.seed
+    }
+
+    /// Check the public key an implementation derived from the seed against the one of the key
+    /// file.
+    pub fn verify_public(&self, public: &[u8]) -> Result<(), KeyError> {
+        match &self.public {
+            Some(stored) if stored.as_slice() != public => Err(KeyError::PublicKeyMismatch),
+            _ => Ok(()),
+        }
+    }
+
+    /// Read a raw seed or a protobuf encoded keypair.
+    pub fn read(path: &Path) -> Result<Self, KeyError> {
+        let bytes = std::fs::read(path).map_err(|error| KeyError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        if let Ok(seed) = <[u8; SEED_LEN]>::try_from(bytes.as_slice()) {
+            return Ok(Self::from_seed(seed));
+        }
+        Self::from_protobuf(&bytes).map_err(|reason| KeyError::InvalidKeyFile {
+            path: path.to_path_buf(),
+            reason,
+        })
+    }
+
+    /// Write the keypair protobuf encoded, as libp2p does, refusing to overwrite a file.
+    ///
+    /// The file is only readable by its owner.
+    pub fn write(&self, path: &Path, public: &[u8; SEED_LEN]) -> Result<(), KeyError> {
+        let io_error = |error| KeyError::Io {
+            path: path.to_path_buf(),
+            error,
+        };
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options
+            .open(path)
+            .and_then(|mut file| file.write_all(&self.to_protobuf(public)))
+            .map_err(io_error)
+    }
+
+    /// The libp2p `PrivateKey` message of the keypair: the key type, then the seed followed by
+    /// the public key.
+    pub fn to_protobuf(&self, public: &[u8; SEED_LEN]) -> Vec<u8> {
+        let mut encoded = vec![0x08, KEY_TYPE_ED25519 as u8, 0x12, KEYPAIR_LEN as u8];
+        encoded.extend_from_slice(&self.seed);
+        encoded.extend_from_slice(public);
+        encoded
+    }
+
+    /// Decode a libp2p `PrivateKey` message of an ed25519 keypair, or of a lone seed.
+    fn from_protobuf(mut bytes: &[u8]) -> Result<Self, String> {
+        let mut key_type = None;
+        let mut data = None;
+        while !bytes.is_empty() {
+            let tag = read_varint(&mut bytes)?;
+            match (tag >> 3, tag & 0x7) {
+                (1, 0) => key_type = Some(read_varint(&mut bytes)?),
+                (field, 0) => {
+                    read_varint(&mut bytes).map_err(|error| format!("field {field}: {error}"))?;
+                }
+                (field, 2) => {
+                    let len = read_varint(&mut bytes)? as usize;
+                    if len > bytes.len() {
+                        return Err(format!("field {field} is truncated"));
+                    }
+                    let (value, rest) = bytes.split_at(len);
+                    if field == 2 {
+                        data = Some(value);
+                    }
+                    bytes = rest;
+                }
+                (_, wire_type) => return Err(format!("unexpected wire type {wire_type}")),
+            }
+        }
+
+        match key_type {
+            Some(KEY_TYPE_ED25519) => {}
+            Some(key_type) => return Err(format!("key type {key_type} is not ed25519")),
+            None => return Err("no key type".to_string()),
+        }
+        let data = data.ok_or("the keypair has no key data")?;
+        let (seed, public) = match data.len() {
+            SEED_LEN => (data, None),
+            KEYPAIR_LEN => (&data[..SEED_LEN], Some(&data[SEED_LEN..])),
+            len => return Err(format!("{len} bytes of ed25519 key data")),
+        };
+        let seed = seed.try_into().expect("seed length");
+        let public = public.map(|public| public.try_into().expect("public key length"));
+        Ok(Self { seed, public })
+    }
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, String> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first().ok_or("truncated varint")?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("varint longer than 64 bits".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passphrases_are_zero_padded() {
+        let key = NodeKey::from_passphrase("secret").unwrap();
+        assert_eq!(&key.seed()[..6], b"secret");
+        assert!(key.seed()[6..].iter().all(|&byte| byte == 0));
+
+        assert!(NodeKey::from_passphrase(&"a".repeat(32)).is_ok());
+        assert!(matches!(
+            NodeKey::from_passphrase(&"a".repeat(33)),
+            Err(KeyError::PassphraseTooLong(33))
+        ));
+    }
+
+    #[test]
+    fn protobuf_keypair_round_trip() {
+        let key = NodeKey::from_seed([7; SEED_LEN]);
+        let public = [9; SEED_LEN];
+        let decoded = NodeKey::from_protobuf(&key.to_protobuf(&public)).unwrap();
+        assert_eq!(decoded.seed(), key.seed());
+        assert!(decoded.verify_public(&public).is_ok());
+        assert!(decoded.verify_public(&[8; SEED_LEN]).is_err());
+    }
+
+    #[test]
+    fn rejects_other_key_types() {
+        // An RSA `PrivateKey` message, key type 0.
+        let error = NodeKey::from_protobuf(&[0x08, 0x00, 0x12, 0x01, 0x00]).unwrap_err();
+        assert!(error.contains("not ed25519"), "{error}");
+    }
+}
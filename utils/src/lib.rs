@@ -1,7 +1,50 @@
 use clap::Parser as ClapParser;
 use clap::ValueEnum;
+use clap::{CommandFactory, FromArgMatches, error::ErrorKind};
 use std::time::Duration;
 
+mod bandwidth_report;
+mod compare;
+mod engine;
+mod error;
+mod histogram;
+mod key;
+mod payload;
+mod protocol;
+mod ready;
+mod report;
+mod result;
+mod setup;
+mod stats;
+mod units;
+mod usage;
+
+pub use bandwidth_report::{BandwidthReport, Pair};
+pub use compare::{Comparison, Metric, Significance, Unmatched, Verdict, compare, mann_whitney_u};
+pub use engine::{client_mode, server_mode};
+pub use error::PerfError;
+pub use histogram::{LatencyHistogram, log_histogram};
+pub use key::{KeyError, KeyOpts, NodeKey, SEED_LEN};
+pub use payload::{DEFAULT_PAYLOAD_SEED, PayloadGenerator, PayloadVerifier};
+pub use protocol::{
+    MAX_CHUNK_SIZE, MAX_LATENCY_MESSAGE_SIZE, ProtocolVersion, REQUEST_ACCEPTED, REQUEST_REJECTED,
+    RequestHeader,
+};
+pub use ready::ServerReady;
+pub use report::{ByteClock, IntervalSample, SideReport, TransferReport, log_side_by_side};
+pub use result::{
+    Case, DirectionResult, IntervalResult, LatencyResult, PerfResult, Requested, SetupResult,
+    UsageResult,
+};
+pub use setup::{ConnectionSetup, SetupEvent, SetupTimeline};
+pub use stats::{
+    LatencyStats, RunStats, ThroughputSummary, TimedMeter, TimedTransfer, fairness_index,
+    log_connections, log_duplex, log_iterations, log_latency, log_parallel, log_setup, log_sweep,
+    log_timed,
+};
+pub use units::{Bandwidth, ByteSize, UnitSystem, set_units, units};
+pub use usage::{ResourceUsage, UsageMeter, log_usage};
+
 /// Command for interacting with the CLI.
 #[derive(Debug, ClapParser)]
 pub enum Command {
@@ -13,6 +56,67 @@ pub enum Command {
 
     /// Start the performance in client mode.
     ClientSubstream(ClientSubstreamOpts),
+
+    /// Print the peer ID of a node key.
+    PeerId(KeyOpts),
+
+    /// Generate a random ed25519 keypair and write it protobuf encoded, to be passed with
+    /// `--key-file`.
+    GenerateKey(GenerateKeyOpts),
+
+    /// Run a server and a client in the same process, connected over localhost.
+    ///
+    /// Takes the client options, the client dials the server wherever it listens.
+    #[command(mut_arg("server_address", |arg| arg.required(false).hide(true).default_value("")))]
+    Loopback(ClientOpts),
+}
+
+impl Command {
+    /// Parse the command line, exiting with a usage error on combinations of values clap cannot
+    /// reject on its own.
+    pub fn parse_checked() -> Self {
+        let mut cli = Self::command();
+        let matches = cli.get_matches_mut();
+        let command = Self::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+        if let Err(message) = command.check() {
+            let name = matches.subcommand_name().unwrap_or_default();
+            cli.find_subcommand_mut(name)
+                .expect("the command is one of the subcommands")
+                .error(ErrorKind::ArgumentConflict, message)
+                .exit();
+        }
+        command
+    }
+
+    fn check(&self) -> Result<(), &'static str> {
+        if let Command::Client(opts) | Command::Loopback(opts) = self
+            && opts.verify
+            && opts.protocol == PerfProtocol::Perf
+        {
+            return Err(
+                "the argument '--verify' cannot be used with '--protocol perf', payload \
+                 verification requires /litep2p-perf/2.0.0",
+            );
+        }
+        Ok(())
+    }
+
+    /// How the command reports its results.
+    pub fn output(&self) -> OutputFormat {
+        match self {
+            Command::Client(opts) | Command::Loopback(opts) => opts.output,
+            _ => OutputFormat::Text,
+        }
+    }
+
+    /// The prefixes the command prints byte sizes and bandwidths with.
+    pub fn units(&self) -> UnitSystem {
+        match self {
+            Command::Server(opts) => opts.units,
+            Command::Client(opts) | Command::Loopback(opts) => opts.units,
+            _ => UnitSystem::Si,
+        }
+    }
 }
 
 /// The transport layer to use for the connection.
@@ -31,6 +135,133 @@ pub enum TransportLayer {
     WebRTC,
 }
 
+/// How the payload is handed to the substream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum WriteStrategy {
+    /// One `write_all` call per chunk.
+    #[clap(name = "write-all")]
+    WriteAll,
+
+    /// Vectored writes of several chunks per call.
+    #[clap(name = "vectored")]
+    Vectored,
+
+    /// One write of the whole payload followed by an explicit flush.
+    ///
+    /// The whole payload is allocated up front, payloads above 64 MiB are written 64 MiB at a
+    /// time.
+    #[clap(name = "single")]
+    Single,
+}
+
+/// The protocol spoken by the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PerfProtocol {
+    /// `/litep2p-perf/2.0.0`, falling back to `/litep2p-perf/1.0.0`.
+    #[clap(name = "litep2p-perf")]
+    Litep2pPerf,
+
+    /// `/perf/1.0.0`, the libp2p perf protocol.
+    #[clap(name = "perf")]
+    Perf,
+}
+
+/// When the client-substream client requests the next substream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OpenPattern {
+    /// Request all substreams at once, without waiting for any of them to open.
+    #[clap(name = "pipelined")]
+    Pipelined,
+
+    /// Request each substream once the previous one opened.
+    #[clap(name = "sequential")]
+    Sequential,
+}
+
+/// How the client reports its results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Log lines and tables.
+    #[clap(name = "text")]
+    Text,
+
+    /// One [`PerfResult`] JSON object per line on the standard output, the logs go to the
+    /// standard error.
+    #[clap(name = "json")]
+    Json,
+}
+
+impl OutputFormat {
+    /// Where the logs go, out of the way of the JSON results.
+    pub fn log_writer(self) -> Box<dyn std::io::Write> {
+        match self {
+            OutputFormat::Text => Box::new(std::io::stdout()),
+            OutputFormat::Json => Box::new(std::io::stderr()),
+        }
+    }
+}
+
+/// Options controlling how the payload is written to and read from the substream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::Args)]
+pub struct TransferOpts {
+    /// The size of the chunks used to write and read the payload.
+    #[clap(long, default_value = "1024", value_parser = parse_chunk_size)]
+    pub chunk_size: usize,
+
+    /// The strategy used to write the payload.
+    #[clap(long, default_value = "write-all")]
+    pub write_strategy: WriteStrategy,
+
+    /// Report the throughput of each direction every given number of seconds during the
+    /// transfer.
+    #[clap(long, value_parser = parse_interval)]
+    pub interval: Option<Duration>,
+}
+
+impl Default for TransferOpts {
+    fn default() -> Self {
+        Self {
+            chunk_size: 1024,
+            write_strategy: WriteStrategy::WriteAll,
+            interval: None,
+        }
+    }
+}
+
+fn parse_chunk_size(value: &str) -> Result<usize, String> {
+    match value.parse::<ByteSize>()? {
+        ByteSize(0) => Err("chunk size must be greater than zero".to_string()),
+        ByteSize(size) if size > MAX_CHUNK_SIZE => Err(format!(
+            "chunk size must be at most {}",
+            ByteSize(MAX_CHUNK_SIZE).format(UnitSystem::Iec)
+        )),
+        ByteSize(size) => usize::try_from(size).map_err(|error| error.to_string()),
+    }
+}
+
+fn parse_interval(value: &str) -> Result<Duration, String> {
+    match parse_seconds(value)? {
+        interval if interval.is_zero() => Err("interval must be greater than zero".to_string()),
+        interval => Ok(interval),
+    }
+}
+
+/// Parse a number of seconds, rounded up to whole milliseconds as durations are sent in
+/// milliseconds.
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    let seconds = value.parse::<f64>().map_err(|error| error.to_string())?;
+    if seconds.is_nan() || seconds < 0.0 {
+        return Err("expected a non-negative number of seconds".to_string());
+    }
+    let duration = Duration::try_from_secs_f64(seconds).map_err(|error| error.to_string())?;
+    if !duration.is_zero() && duration < Duration::from_millis(1) {
+        return Err("durations below 0.001 seconds are not supported".to_string());
+    }
+    let millis = u64::try_from(duration.as_nanos().div_ceil(1_000_000))
+        .map_err(|error| error.to_string())?;
+    Ok(Duration::from_millis(millis))
+}
+
 /// The server options.
 #[derive(Debug, ClapParser)]
 pub struct ServerOpts {
@@ -38,13 +269,40 @@ pub struct ServerOpts {
     #[clap(long, short)]
     pub listen_address: String,
 
-    /// The node key used to derive the server peer ID.
-    #[clap(long, short)]
-    pub node_key: String,
+    #[clap(flatten)]
+    pub key: KeyOpts,
 
     /// The transport layer to use for the connection.
     #[clap(long, default_value = "tcp")]
     pub transport_layer: TransportLayer,
+
+    /// Verify the uploaded payload of `/litep2p-perf/1.0.0` clients against the seeded
+    /// pseudo-random stream.
+    ///
+    /// Newer clients request verification themselves.
+    #[clap(long)]
+    pub verify: bool,
+
+    #[clap(flatten)]
+    pub transfer: TransferOpts,
+
+    /// The prefixes used to print byte sizes and bandwidths.
+    #[clap(long, default_value = "si")]
+    pub units: UnitSystem,
+
+    /// Once listening, write the listen addresses and the peer ID to this file as a JSON
+    /// object.
+    ///
+    /// The same object is always printed as a single line on the standard output.
+    #[clap(long)]
+    pub ready_file: Option<std::path::PathBuf>,
+}
+
+/// The key generation options.
+#[derive(Debug, ClapParser)]
+pub struct GenerateKeyOpts {
+    /// The file to write the keypair to, which must not exist yet.
+    pub key_file: std::path::PathBuf,
 }
 
 /// The client options.
@@ -54,17 +312,264 @@ pub struct ClientOpts {
     #[clap(long, short)]
     pub server_address: String,
 
-    /// The uploaded bytes.
-    #[clap(long)]
-    pub upload_bytes: usize,
+    /// The uploaded bytes, such as `16777216`, `16MiB` or `1G`.
+    ///
+    /// Required unless the run is time bounded or measures the latency.
+    #[clap(long, required_unless_present_any = ["upload_duration", "download_duration", "latency_messages"])]
+    pub upload_bytes: Option<ByteSize>,
 
-    /// The downloaded bytes.
-    #[clap(long)]
-    pub download_bytes: usize,
+    /// The downloaded bytes, such as `16777216`, `16MiB` or `1G`.
+    ///
+    /// Required unless the run is time bounded or measures the latency.
+    #[clap(long, required_unless_present_any = ["upload_duration", "download_duration", "latency_messages"])]
+    pub download_bytes: Option<ByteSize>,
+
+    /// Upload for the given number of seconds instead of a fixed number of bytes.
+    ///
+    /// Time bounded transfers require a server speaking `/litep2p-perf/2.0.0`.
+    #[clap(long, value_parser = parse_seconds, conflicts_with_all = ["upload_bytes", "download_bytes"])]
+    pub upload_duration: Option<Duration>,
+
+    /// Download for the given number of seconds instead of a fixed number of bytes.
+    #[clap(long, value_parser = parse_seconds, conflicts_with_all = ["upload_bytes", "download_bytes"])]
+    pub download_duration: Option<Duration>,
 
     /// The transport layer to use for the connection.
     #[clap(long, default_value = "tcp")]
     pub transport_layer: TransportLayer,
+
+    /// The protocol to run.
+    ///
+    /// `/perf/1.0.0` only transfers a fixed number of bytes, one direction after the other,
+    /// without payload verification.
+    #[clap(long, default_value = "litep2p-perf")]
+    pub protocol: PerfProtocol,
+
+    /// Send a seeded pseudo-random payload and verify the downloaded one.
+    ///
+    /// A server only speaking `/litep2p-perf/1.0.0` must be started with `--verify` as well.
+    #[clap(long)]
+    pub verify: bool,
+
+    #[clap(flatten)]
+    pub transfer: TransferOpts,
+
+    /// Measure round trip times instead of throughput, by sending this many messages that the
+    /// server echoes back one at a time.
+    ///
+    /// Requires a server speaking `/litep2p-perf/2.0.0`.
+    #[clap(long, conflicts_with_all = ["upload_bytes", "download_bytes", "upload_duration", "download_duration", "duplex"])]
+    pub latency_messages: Option<u64>,
+
+    /// Size in bytes of each message of a latency run.
+    #[clap(long, default_value = "32", requires = "latency_messages", value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..=MAX_LATENCY_MESSAGE_SIZE))]
+    pub message_size: u64,
+
+    /// Upload and download at the same time over the same substream.
+    ///
+    /// Requires a server speaking `/litep2p-perf/2.0.0`.
+    #[clap(long, conflicts_with_all = ["upload_duration", "download_duration"])]
+    pub duplex: bool,
+
+    /// Run the transfer over this many substreams of the connection at the same time.
+    ///
+    /// Each substream transfers the requested bytes or runs for the requested duration.
+    #[clap(long, default_value = "1", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub parallel: usize,
+
+    /// Open this many connections to the server, each from its own client identity, and
+    /// run the transfer on all of them at the same time.
+    #[clap(long, default_value = "1", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), conflicts_with = "sweep_chunk_sizes")]
+    pub connections: usize,
+
+    /// Repeat every run this many times over the connection and report summary statistics of
+    /// the throughput.
+    #[clap(long, default_value = "1", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub iterations: usize,
+
+    /// Run every run this many times before the measured iterations and discard the results.
+    #[clap(long, default_value = "0")]
+    pub warmup: usize,
+
+    /// Repeat the run once for each of the comma separated chunk sizes and report
+    /// the throughput per chunk size.
+    ///
+    /// A server only speaking `/litep2p-perf/1.0.0` writes the download with its own chunk size.
+    #[clap(long, value_delimiter = ',', value_parser = parse_chunk_size)]
+    pub sweep_chunk_sizes: Vec<usize>,
+
+    /// How to report the results.
+    #[clap(long, default_value = "text")]
+    pub output: OutputFormat,
+
+    /// The prefixes used to print byte sizes and bandwidths.
+    #[clap(long, default_value = "si")]
+    pub units: UnitSystem,
+}
+
+impl ClientOpts {
+    /// The workload requested by the client.
+    pub fn workload(&self) -> Workload {
+        if let Some(messages) = self.latency_messages {
+            return Workload::Latency {
+                messages,
+                size: self.message_size,
+            };
+        }
+        if self.upload_duration.is_some() || self.download_duration.is_some() {
+            return Workload::Duration {
+                upload: self.upload_duration.unwrap_or_default(),
+                download: self.download_duration.unwrap_or_default(),
+            };
+        }
+
+        Workload::Bytes {
+            upload: self.upload_bytes.unwrap_or_default().0,
+            download: self.download_bytes.unwrap_or_default().0,
+        }
+    }
+
+    /// The parameters of each run requested by the client.
+    pub fn runs(&self) -> Vec<RunParams> {
+        let run = RunParams {
+            workload: self.workload(),
+            verify: self.verify,
+            transfer: self.transfer,
+            duplex: self.duplex,
+            parallel: self.parallel,
+            protocol: self.protocol,
+        };
+        if self.sweep_chunk_sizes.is_empty() {
+            return vec![run];
+        }
+
+        self.sweep_chunk_sizes
+            .iter()
+            .map(|&chunk_size| RunParams {
+                transfer: TransferOpts {
+                    chunk_size,
+                    ..self.transfer
+                },
+                ..run
+            })
+            .collect()
+    }
+
+    /// The runs to execute one after the other, each repeated for the warmup and the measured
+    /// iterations.
+    pub fn schedule(&self) -> Vec<RunParams> {
+        self.runs()
+            .into_iter()
+            .flat_map(|run| std::iter::repeat_n(run, self.warmup + self.iterations))
+            .collect()
+    }
+
+    /// Group the measurements of the runs of [`ClientOpts::schedule`] by run, dropping the
+    /// warmup iterations.
+    pub fn measured(&self, stats: Vec<RunStats>) -> Vec<(RunParams, Vec<RunStats>)> {
+        let mut stats = stats.into_iter();
+        self.runs()
+            .into_iter()
+            .map(|run| {
+                let iterations = stats
+                    .by_ref()
+                    .take(self.warmup + self.iterations)
+                    .skip(self.warmup)
+                    .collect();
+                (run, iterations)
+            })
+            .collect()
+    }
+
+    /// The results of the runs of [`ClientOpts::schedule`], warmup iterations included.
+    pub fn results(&self, implementation: &str, stats: &[RunStats]) -> Vec<PerfResult> {
+        let per_run = self.warmup + self.iterations;
+        self.schedule()
+            .iter()
+            .zip(stats)
+            .enumerate()
+            .map(|(index, (params, stats))| PerfResult {
+                run: Some(index),
+                warmup: index % per_run < self.warmup,
+                ..PerfResult::new(
+                    implementation,
+                    self.transport_layer,
+                    params,
+                    self.connections,
+                )
+                .with_stats(stats)
+            })
+            .collect()
+    }
+
+    /// Log the stats of the runs of [`ClientOpts::schedule`], aggregated over the connections,
+    /// and print their results in JSON mode.
+    pub fn report(&self, implementation: &str, stats: Vec<RunStats>) {
+        stats.iter().for_each(log_setup);
+        if self.connections > 1 {
+            stats.iter().for_each(log_connections);
+        }
+
+        if self.output == OutputFormat::Json {
+            for result in self.results(implementation, &stats) {
+                result.print();
+            }
+        }
+
+        let results = self.measured(stats);
+        if self.iterations > 1 {
+            for (_, iterations) in &results {
+                log_iterations(iterations);
+            }
+        }
+        if !self.sweep_chunk_sizes.is_empty() {
+            log_sweep(&results);
+        }
+    }
+
+    /// The result of a client that failed before reporting its runs.
+    pub fn failed(&self, implementation: &str, error: &dyn std::fmt::Display) -> PerfResult {
+        let params = self.runs().remove(0);
+        PerfResult::new(
+            implementation,
+            self.transport_layer,
+            &params,
+            self.connections,
+        )
+        .with_error(error)
+    }
+}
+
+/// What a client run transfers in each direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Workload {
+    /// Transfer a fixed number of bytes.
+    Bytes { upload: u64, download: u64 },
+
+    /// Transfer until the receiving side stops the sender after the given duration.
+    ///
+    /// A zero duration skips the direction.
+    Duration {
+        upload: Duration,
+        download: Duration,
+    },
+
+    /// Send messages of `size` bytes one at a time and wait for the server to echo each one.
+    Latency { messages: u64, size: u64 },
+}
+
+/// Parameters of a single client run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunParams {
+    pub workload: Workload,
+    pub verify: bool,
+    pub transfer: TransferOpts,
+    /// Transfer a fixed number of bytes in both directions at the same time.
+    pub duplex: bool,
+    /// Number of substreams running the transfer at the same time.
+    pub parallel: usize,
+    /// The protocol the run is requested with.
+    pub protocol: PerfProtocol,
 }
 
 /// The client options.
@@ -78,42 +583,53 @@ pub struct ClientSubstreamOpts {
     #[clap(long)]
     pub substreams: usize,
 
+    /// Whether to request the substreams all at once or one after the other.
+    #[clap(long, default_value = "pipelined")]
+    pub pattern: OpenPattern,
+
     /// The transport layer to use for the connection.
     #[clap(long, default_value = "tcp")]
     pub transport_layer: TransportLayer,
 }
 
-const KILO: f64 = 1024.0;
-const MEGA: f64 = KILO * 1024.0;
-const GIGA: f64 = MEGA * 1024.0;
-
-pub fn format_bytes(bytes: usize) -> String {
-    let bytes = bytes as f64;
-    if bytes >= GIGA {
-        format!("{:.2} GiB", bytes / GIGA)
-    } else if bytes >= MEGA {
-        format!("{:.2} MiB", bytes / MEGA)
-    } else if bytes >= KILO {
-        format!("{:.2} KiB", bytes / KILO)
-    } else {
-        format!("{} B", bytes)
-    }
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-pub fn format_bandwidth(duration: Duration, bytes: usize) -> String {
-    const KILO: f64 = 1024.0;
-    const MEGA: f64 = KILO * 1024.0;
-    const GIGA: f64 = MEGA * 1024.0;
+    #[test]
+    fn seconds_are_rounded_up_to_milliseconds() {
+        assert_eq!(parse_seconds("0"), Ok(Duration::ZERO));
+        assert_eq!(parse_seconds("0.001"), Ok(Duration::from_millis(1)));
+        assert_eq!(parse_seconds("0.3"), Ok(Duration::from_millis(300)));
+        assert_eq!(parse_seconds("1.0015"), Ok(Duration::from_millis(1002)));
+        assert_eq!(parse_seconds("10"), Ok(Duration::from_secs(10)));
 
-    let bandwidth = (bytes as f64 * 8.0) / duration.as_secs_f64();
+        assert!(parse_seconds("0.0005").is_err());
+        assert!(parse_seconds("-1").is_err());
+        assert!(parse_seconds("NaN").is_err());
+        assert!(parse_seconds("inf").is_err());
+    }
 
-    if bandwidth >= GIGA {
-        format!("{:.2} Gbit/s", bandwidth / GIGA)
-    } else if bandwidth >= MEGA {
-        format!("{:.2} Mbit/s", bandwidth / MEGA)
-    } else if bandwidth >= KILO {
-        format!("{:.2} Kbit/s", bandwidth / KILO)
-    } else {
-        format!("{:.2} bit/s", bandwidth)
+    #[test]
+    fn verify_conflicts_with_the_perf_protocol() {
+        let check = |protocol: &str| {
+            Command::try_parse_from([
+                "perf",
+                "client",
+                "--server-address",
+                "/ip4/127.0.0.1/tcp/1",
+                "--upload-bytes",
+                "1",
+                "--download-bytes",
+                "1",
+                "--protocol",
+                protocol,
+                "--verify",
+            ])
+            .unwrap()
+            .check()
+        };
+        assert!(check("litep2p-perf").is_ok());
+        assert!(check("perf").is_err());
     }
 }
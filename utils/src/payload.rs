@@ -0,0 +1,141 @@
+/// Default seed of the payload stream when verification is enabled.
+pub const DEFAULT_PAYLOAD_SEED: u64 = 0x6c69_7465_7032_7021;
+
+/// Compute the 8 pseudo-random bytes of the given block.
+///
+/// Every block is derived independently from the seed (splitmix64), such that the
+/// byte at any offset of the stream can be computed without generating the prefix.
+fn block(seed: u64, index: u64) -> [u8; 8] {
+    let mut z = seed.wrapping_add(index.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (z ^ (z >> 31)).to_be_bytes()
+}
+
+/// Fill `buf` with the bytes of the seeded stream starting at `offset`.
+fn fill_at(seed: u64, offset: u64, buf: &mut [u8]) {
+    let mut offset = offset;
+    let mut written = 0;
+    while written < buf.len() {
+        let bytes = block(seed, offset / 8);
+        let start = (offset % 8) as usize;
+        let len = (8 - start).min(buf.len() - written);
+        buf[written..written + len].copy_from_slice(&bytes[start..start + len]);
+        written += len;
+        offset += len as u64;
+    }
+}
+
+/// Deterministic pseudo-random payload written by the sender in verification mode.
+#[derive(Debug, Clone)]
+pub struct PayloadGenerator {
+    seed: u64,
+    offset: u64,
+}
+
+impl PayloadGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, offset: 0 }
+    }
+
+    /// Fill `buf` with the next bytes of the stream.
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        fill_at(self.seed, self.offset, buf);
+        self.offset += buf.len() as u64;
+    }
+}
+
+/// Checks the received bytes against the stream produced by [`PayloadGenerator`].
+#[derive(Debug, Clone)]
+pub struct PayloadVerifier {
+    seed: u64,
+    offset: u64,
+    expected: Vec<u8>,
+}
+
+impl PayloadVerifier {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            offset: 0,
+            expected: Vec::new(),
+        }
+    }
+
+    /// Number of bytes verified so far.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Verify the next bytes of the stream.
+    ///
+    /// Returns the offset of the first byte which does not match the expected stream.
+    pub fn verify(&mut self, buf: &[u8]) -> Result<(), crate::PerfError> {
+        self.expected.resize(buf.len(), 0);
+        fill_at(self.seed, self.offset, &mut self.expected);
+
+        if let Some(index) = buf
+            .iter()
+            .zip(self.expected.iter())
+            .position(|(received, expected)| received != expected)
+        {
+            return Err(crate::PerfError::PayloadMismatch {
+                offset: self.offset + index as u64,
+            });
+        }
+
+        self.offset += buf.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::PerfError;
+
+    /// The first `len` bytes of the stream, generated in chunks of `chunk` bytes.
+    fn generate(seed: u64, len: usize, chunk: usize) -> Vec<u8> {
+        let mut generator = PayloadGenerator::new(seed);
+        let mut payload = vec![0; len];
+        for chunk in payload.chunks_mut(chunk) {
+            generator.fill(chunk);
+        }
+        payload
+    }
+
+    #[test]
+    fn stream_does_not_depend_on_the_chunk_size() {
+        let payload = generate(DEFAULT_PAYLOAD_SEED, 1000, 1000);
+        for chunk in [1, 3, 8, 13, 64] {
+            assert_eq!(generate(DEFAULT_PAYLOAD_SEED, 1000, chunk), payload);
+        }
+        assert_ne!(generate(DEFAULT_PAYLOAD_SEED + 1, 1000, 1000), payload);
+    }
+
+    #[test]
+    fn verifier_accepts_the_stream_in_any_chunks() {
+        let payload = generate(DEFAULT_PAYLOAD_SEED, 1000, 1000);
+        let mut verifier = PayloadVerifier::new(DEFAULT_PAYLOAD_SEED);
+        for chunk in payload.chunks(7) {
+            verifier.verify(chunk).unwrap();
+        }
+        assert_eq!(verifier.offset(), 1000);
+    }
+
+    #[test]
+    fn verifier_reports_the_first_bad_offset_across_chunks() {
+        let mut payload = generate(DEFAULT_PAYLOAD_SEED, 100, 100);
+        payload[45] ^= 1;
+        payload[70] ^= 1;
+
+        let mut verifier = PayloadVerifier::new(DEFAULT_PAYLOAD_SEED);
+        verifier.verify(&payload[..30]).unwrap();
+        assert!(matches!(
+            verifier.verify(&payload[30..60]),
+            Err(PerfError::PayloadMismatch { offset: 45 })
+        ));
+        assert_eq!(verifier.offset(), 30);
+    }
+}
@@ -0,0 +1,297 @@
+use std::time::Duration;
+
+use crate::{PerfError, RunParams, Workload};
+
+/// Version of the perf protocol negotiated on a substream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolVersion {
+    /// `/litep2p-perf/1.0.0`: bare big-endian byte counts.
+    V1,
+
+    /// `/litep2p-perf/2.0.0`: the substream starts with a [`RequestHeader`].
+    V2,
+
+    /// `/perf/1.0.0`: the libp2p perf protocol.
+    ///
+    /// The client sends the number of bytes to download as a big-endian u64 and the upload,
+    /// then closes its write side. The server sends the download once the upload ended.
+    Perf,
+}
+
+/// Sent by the server once it accepted the request header.
+pub const REQUEST_ACCEPTED: u8 = 0;
+
+/// Sent by the server when it does not support the requested mode or flags.
+pub const REQUEST_REJECTED: u8 = 1;
+
+/// Largest message of a latency run, the server holds one message in memory at a time.
+pub const MAX_LATENCY_MESSAGE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Largest chunk size, the server allocates its read and write buffers from the chunk size.
+pub const MAX_CHUNK_SIZE: u64 = 1024 * 1024;
+
+const MODE_BYTES: u8 = 0;
+const MODE_DURATION: u8 = 1;
+const MODE_LATENCY: u8 = 2;
+
+const FLAG_VERIFY: u8 = 1 << 0;
+const FLAG_UPLOAD_ACK: u8 = 1 << 1;
+const FLAG_SERVER_REPORT: u8 = 1 << 2;
+const FLAG_DUPLEX: u8 = 1 << 3;
+const KNOWN_FLAGS: u8 = FLAG_VERIFY | FLAG_UPLOAD_ACK | FLAG_SERVER_REPORT | FLAG_DUPLEX;
+
+/// Request sent by the client at the start of a `/litep2p-perf/2.0.0` substream.
+///
+/// The header is encoded as the mode (u8), the flags (u8), the chunk size (u32),
+/// the request id (u64) and the upload and download amounts (u64 each), all big-endian.
+/// The amounts are byte counts or durations in milliseconds depending on the mode, or the
+/// number and the size of the echoed messages of a latency run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestHeader {
+    /// Identifies the run in the logs of both sides and seeds the verified payload.
+    pub request_id: u64,
+    /// What to transfer in each direction.
+    pub workload: Workload,
+    /// Send and check the seeded payload in both directions.
+    pub verify: bool,
+    /// Acknowledge a fixed size upload once it is fully received.
+    ///
+    /// The acknowledgement is the number of received bytes as a big-endian u64.
+    pub upload_ack: bool,
+    /// Send the server view of the run once the download is complete.
+    pub server_report: bool,
+    /// Transfer a fixed number of bytes in both directions at the same time.
+    ///
+    /// The upload is not acknowledged in duplex mode.
+    pub duplex: bool,
+    /// Chunk size used by the server to read the upload and write the download.
+    pub chunk_size: u32,
+}
+
+impl RequestHeader {
+    /// Length of the encoded header.
+    pub const ENCODED_LEN: usize = 30;
+
+    pub fn new(request_id: u64, run: &RunParams) -> Self {
+        Self {
+            request_id,
+            workload: run.workload,
+            verify: run.verify,
+            upload_ack: !run.duplex,
+            server_report: true,
+            duplex: run.duplex,
+            chunk_size: u32::try_from(run.transfer.chunk_size)
+                .expect("chunk sizes are bounded by MAX_CHUNK_SIZE"),
+        }
+    }
+
+    /// Seed of the payload, if the payload is verified.
+    pub fn seed(&self) -> Option<u64> {
+        self.verify.then_some(self.request_id)
+    }
+
+    pub fn encode(&self) -> [u8; Self::ENCODED_LEN] {
+        let (mode, upload, download) = match self.workload {
+            Workload::Bytes { upload, download } => (MODE_BYTES, upload, download),
+            Workload::Duration { upload, download } => (
+                MODE_DURATION,
+                upload.as_millis() as u64,
+                download.as_millis() as u64,
+            ),
+            Workload::Latency { messages, size } => (MODE_LATENCY, messages, size),
+        };
+        let mut flags = 0;
+        if self.verify {
+            flags |= FLAG_VERIFY;
+        }
+        if self.upload_ack {
+            flags |= FLAG_UPLOAD_ACK;
+        }
+        if self.server_report {
+            flags |= FLAG_SERVER_REPORT;
+        }
+        if self.duplex {
+            flags |= FLAG_DUPLEX;
+        }
+
+        let mut buf = [0u8; Self::ENCODED_LEN];
+        buf[0] = mode;
+        buf[1] = flags;
+        buf[2..6].copy_from_slice(&self.chunk_size.to_be_bytes());
+        buf[6..14].copy_from_slice(&self.request_id.to_be_bytes());
+        buf[14..22].copy_from_slice(&upload.to_be_bytes());
+        buf[22..].copy_from_slice(&download.to_be_bytes());
+        buf
+    }
+
+    /// Decode a request header, rejecting unknown modes and flags.
+    pub fn decode(buf: &[u8; Self::ENCODED_LEN]) -> Result<Self, PerfError> {
+        let u64_at =
+            |start: usize| u64::from_be_bytes(buf[start..start + 8].try_into().expect("8 bytes"));
+        let chunk_size = u32::from_be_bytes(buf[2..6].try_into().expect("4 bytes"));
+        let (upload, download) = (u64_at(14), u64_at(22));
+
+        let workload = match buf[0] {
+            MODE_BYTES => Workload::Bytes { upload, download },
+            MODE_DURATION => Workload::Duration {
+                upload: Duration::from_millis(upload),
+                download: Duration::from_millis(download),
+            },
+            MODE_LATENCY if download == 0 => {
+                return Err(PerfError::Protocol("zero latency message size"));
+            }
+            MODE_LATENCY if download > MAX_LATENCY_MESSAGE_SIZE => {
+                return Err(PerfError::Protocol("latency message too large"));
+            }
+            MODE_LATENCY => Workload::Latency {
+                messages: upload,
+                size: download,
+            },
+            _ => return Err(PerfError::Protocol("unknown request mode")),
+        };
+        if buf[1] & FLAG_DUPLEX != 0 && buf[0] != MODE_BYTES {
+            return Err(PerfError::Protocol(
+                "duplex requires a fixed number of bytes",
+            ));
+        }
+        if buf[1] & !KNOWN_FLAGS != 0 {
+            return Err(PerfError::Protocol("unknown request flags"));
+        }
+        if chunk_size == 0 {
+            return Err(PerfError::Protocol("zero chunk size"));
+        }
+        if u64::from(chunk_size) > MAX_CHUNK_SIZE {
+            return Err(PerfError::Protocol("chunk size too large"));
+        }
+
+        Ok(Self {
+            request_id: u64_at(6),
+            workload,
+            verify: buf[1] & FLAG_VERIFY != 0,
+            upload_ack: buf[1] & FLAG_UPLOAD_ACK != 0,
+            server_report: buf[1] & FLAG_SERVER_REPORT != 0,
+            duplex: buf[1] & FLAG_DUPLEX != 0,
+            chunk_size,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(workload: Workload) -> RequestHeader {
+        RequestHeader {
+            request_id: 0x0102_0304_0506_0708,
+            workload,
+            verify: true,
+            upload_ack: true,
+            server_report: true,
+            duplex: false,
+            chunk_size: 65536,
+        }
+    }
+
+    /// Decode `header` once `edit` changed its encoding.
+    fn decode_edited(
+        header: RequestHeader,
+        edit: impl FnOnce(&mut [u8]),
+    ) -> Result<RequestHeader, PerfError> {
+        let mut buf = header.encode();
+        edit(&mut buf);
+        RequestHeader::decode(&buf)
+    }
+
+    fn rejection(result: Result<RequestHeader, PerfError>) -> &'static str {
+        match result {
+            Err(PerfError::Protocol(reason)) => reason,
+            other => panic!("expected a protocol error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn header_round_trips() {
+        let workloads = [
+            Workload::Bytes {
+                upload: 1,
+                download: u64::MAX,
+            },
+            Workload::Duration {
+                upload: Duration::from_millis(1500),
+                download: Duration::ZERO,
+            },
+            Workload::Latency {
+                messages: 100,
+                size: MAX_LATENCY_MESSAGE_SIZE,
+            },
+        ];
+        for workload in workloads {
+            let header = header(workload);
+            assert_eq!(RequestHeader::decode(&header.encode()).unwrap(), header);
+        }
+
+        let duplex = RequestHeader {
+            duplex: true,
+            upload_ack: false,
+            verify: false,
+            ..header(Workload::Bytes {
+                upload: 10,
+                download: 20,
+            })
+        };
+        assert_eq!(RequestHeader::decode(&duplex.encode()).unwrap(), duplex);
+    }
+
+    #[test]
+    fn invalid_headers_are_rejected() {
+        let bytes = header(Workload::Bytes {
+            upload: 10,
+            download: 20,
+        });
+        let latency = header(Workload::Latency {
+            messages: 10,
+            size: 32,
+        });
+
+        assert_eq!(
+            rejection(decode_edited(bytes, |buf| buf[2..6].fill(0))),
+            "zero chunk size"
+        );
+        assert_eq!(
+            rejection(decode_edited(bytes, |buf| {
+                buf[2..6].copy_from_slice(&(MAX_CHUNK_SIZE as u32 + 1).to_be_bytes())
+            })),
+            "chunk size too large"
+        );
+        assert_eq!(
+            rejection(decode_edited(bytes, |buf| buf[1] |= 1 << 7)),
+            "unknown request flags"
+        );
+        assert_eq!(
+            rejection(decode_edited(bytes, |buf| buf[0] = 3)),
+            "unknown request mode"
+        );
+        assert_eq!(
+            rejection(decode_edited(latency, |buf| buf[22..].fill(0))),
+            "zero latency message size"
+        );
+        assert_eq!(
+            rejection(decode_edited(latency, |buf| {
+                buf[22..].copy_from_slice(&(MAX_LATENCY_MESSAGE_SIZE + 1).to_be_bytes())
+            })),
+            "latency message too large"
+        );
+        for header in [
+            latency,
+            header(Workload::Duration {
+                upload: Duration::from_secs(1),
+                download: Duration::from_secs(1),
+            }),
+        ] {
+            assert_eq!(
+                rejection(decode_edited(header, |buf| buf[1] |= FLAG_DUPLEX)),
+                "duplex requires a fixed number of bytes"
+            );
+        }
+    }
+}
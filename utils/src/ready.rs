@@ -0,0 +1,71 @@
+use std::{fmt::Display, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+/// What a server announces once it listens, printed as a single JSON line and written to
+/// `--ready-file`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerReady {
+    /// Implementation of the server, `litep2p` or `libp2p`.
+    pub implementation: String,
+    pub peer_id: String,
+    /// The addresses the server listens on, each ending with `/p2p/<peer id>`.
+    pub listen_addresses: Vec<String>,
+}
+
+impl ServerReady {
+    /// The announcement of a server listening on `addresses`, with the peer ID appended to the
+    /// addresses that lack it.
+    pub fn new(
+        implementation: &str,
+        peer_id: impl Display,
+        addresses: impl IntoIterator<Item = impl Display>,
+    ) -> Self {
+        let peer_id = peer_id.to_string();
+        let suffix = format!("/p2p/{peer_id}");
+        let listen_addresses = addresses
+            .into_iter()
+            .map(|address| {
+                let address = address.to_string();
+                if address.ends_with(&suffix) {
+                    address
+                } else {
+                    address + &suffix
+                }
+            })
+            .collect();
+
+        Self {
+            implementation: implementation.to_string(),
+            peer_id,
+            listen_addresses,
+        }
+    }
+
+    /// Print the announcement as a single JSON line on the standard output and write it to
+    /// `ready_file`, if any.
+    ///
+    /// The file is written next to its final path and renamed, so it only ever appears
+    /// complete.
+    pub fn announce(&self, ready_file: Option<&Path>) -> io::Result<()> {
+        let line = serde_json::to_string(self).expect("announcements serialize to JSON");
+        println!("{line}");
+
+        if let Some(path) = ready_file {
+            let mut partial = path.as_os_str().to_owned();
+            partial.push(".partial");
+            std::fs::write(&partial, format!("{line}\n"))?;
+            std::fs::rename(&partial, path)?;
+        }
+        Ok(())
+    }
+
+    /// Parse an announcement from a line of the server output, `None` for log lines.
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if !line.starts_with('{') {
+            return None;
+        }
+        serde_json::from_str(line).ok()
+    }
+}
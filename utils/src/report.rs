@@ -0,0 +1,402 @@
+use std::time::{Duration, Instant};
+
+use crate::{Bandwidth, ByteSize};
+
+/// Records when the first and the last payload byte of one direction went through.
+#[derive(Debug, Clone)]
+pub struct ByteClock {
+    start: Instant,
+    report: TransferReport,
+    sampler: Option<Sampler>,
+}
+
+impl ByteClock {
+    /// Timestamps are taken relative to `start`.
+    pub fn new(start: Instant) -> Self {
+        Self {
+            start,
+            report: TransferReport::default(),
+            sampler: None,
+        }
+    }
+
+    /// Also sample the throughput every `interval` from the first byte on, and log each
+    /// sample as `direction`. Nothing is sampled without an interval.
+    pub fn sampled(mut self, interval: Option<Duration>, direction: &'static str) -> Self {
+        self.sampler = interval.map(|interval| Sampler {
+            interval,
+            direction,
+            start: None,
+            bytes: 0,
+            samples: Vec::new(),
+        });
+        self
+    }
+
+    /// Record `len` bytes that were just read from or written to the substream.
+    pub fn record(&mut self, len: usize) {
+        let now = self.start.elapsed();
+        if let Some(sampler) = self.sampler.as_mut() {
+            sampler.record(now, len as u64);
+        }
+        self.report.bytes += len as u64;
+        self.report.first_byte.get_or_insert(now);
+        self.report.last_byte = Some(now);
+    }
+
+    /// Close the running interval at the last byte, once the direction is complete.
+    pub fn finish(&mut self) {
+        if let (Some(sampler), Some(last_byte)) = (self.sampler.as_mut(), self.report.last_byte) {
+            sampler.finish(last_byte);
+        }
+    }
+
+    /// The throughput samples taken so far.
+    pub fn intervals(&self) -> Vec<IntervalSample> {
+        self.sampler
+            .as_ref()
+            .map(|sampler| sampler.samples.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn report(&self) -> TransferReport {
+        self.report
+    }
+}
+
+/// Splits the bytes of one direction into intervals of a fixed length.
+#[derive(Debug, Clone)]
+struct Sampler {
+    interval: Duration,
+    direction: &'static str,
+    /// Start of the running interval, set by the first byte.
+    start: Option<Duration>,
+    /// Bytes of the running interval.
+    bytes: u64,
+    samples: Vec<IntervalSample>,
+}
+
+impl Sampler {
+    fn record(&mut self, now: Duration, bytes: u64) {
+        let mut start = *self.start.get_or_insert(now);
+        // Intervals without any byte are kept, they show the stalls.
+        while now >= start + self.interval {
+            self.close(start + self.interval);
+            start += self.interval;
+        }
+        self.bytes += bytes;
+    }
+
+    fn finish(&mut self, last_byte: Duration) {
+        if self.bytes > 0 {
+            self.close(last_byte);
+        }
+    }
+
+    fn close(&mut self, end: Duration) {
+        let start = self.start.unwrap_or(end);
+        let sample = IntervalSample {
+            start,
+            end,
+            bytes: self.bytes,
+        };
+        tracing::info!(
+            "{} {:.4}s-{:.4}s {} bandwidth {}",
+            self.direction,
+            start.as_secs_f64(),
+            end.as_secs_f64(),
+            ByteSize(sample.bytes),
+            Bandwidth::from_bits_per_second(sample.bits_per_second()),
+        );
+        self.samples.push(sample);
+        self.start = Some(end);
+        self.bytes = 0;
+    }
+}
+
+/// Bytes of one direction that went through during one sampling interval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntervalSample {
+    /// Start of the interval, relative to the start of the run on this side.
+    pub start: Duration,
+    /// End of the interval, the last byte for the last interval of a direction.
+    pub end: Duration,
+    /// Payload bytes that went through during the interval.
+    pub bytes: u64,
+}
+
+impl IntervalSample {
+    /// Throughput of the interval, in bits per second.
+    pub fn bits_per_second(&self) -> f64 {
+        Bandwidth::new(self.bytes, self.end.saturating_sub(self.start)).bits_per_second()
+    }
+}
+
+/// One side's view of one direction of a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferReport {
+    /// Payload bytes counted by this side.
+    pub bytes: u64,
+    /// When the first payload byte went through, relative to the start of the run on this side.
+    pub first_byte: Option<Duration>,
+    /// When the last payload byte went through, relative to the start of the run on this side.
+    pub last_byte: Option<Duration>,
+}
+
+impl TransferReport {
+    /// Combine the views of substreams or connections that ran at the same time: the bytes are
+    /// summed, the earliest first byte and the latest last byte are kept.
+    pub fn merge(self, other: Self) -> Self {
+        let earliest = |a: Option<Duration>, b: Option<Duration>| match (a, b) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        Self {
+            bytes: self.bytes + other.bytes,
+            first_byte: earliest(self.first_byte, other.first_byte),
+            last_byte: self.last_byte.max(other.last_byte),
+        }
+    }
+
+    /// Time between the first and the last payload byte.
+    pub fn duration(&self) -> Duration {
+        match (self.first_byte, self.last_byte) {
+            (Some(first), Some(last)) => last.saturating_sub(first),
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+/// Length of the implementation name field of an encoded [`SideReport`].
+const IMPLEMENTATION_LEN: usize = 16;
+
+/// Sent in place of a missing timestamp.
+const NO_TIMESTAMP: u64 = u64::MAX;
+
+/// One side's view of a run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SideReport {
+    /// Implementation running on this side, `litep2p` or `libp2p`.
+    pub implementation: String,
+    /// The upload, sent by the client and received by the server.
+    pub upload: TransferReport,
+    /// The download, sent by the server and received by the client.
+    pub download: TransferReport,
+}
+
+impl SideReport {
+    /// Length of the encoded report.
+    pub const ENCODED_LEN: usize = 6 * 8 + IMPLEMENTATION_LEN;
+
+    pub fn new(implementation: &str, upload: &ByteClock, download: &ByteClock) -> Self {
+        Self {
+            implementation: implementation.to_string(),
+            upload: upload.report(),
+            download: download.report(),
+        }
+    }
+
+    /// Combine the views of substreams or connections that ran at the same time, see
+    /// [`TransferReport::merge`].
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            implementation: self.implementation,
+            upload: self.upload.merge(other.upload),
+            download: self.download.merge(other.download),
+        }
+    }
+
+    /// Time from the last upload byte to the first download byte, or from the start of the run
+    /// to the first download byte when no upload byte went through before it.
+    pub fn time_to_first_byte(&self) -> Option<Duration> {
+        let first_byte = self.download.first_byte?;
+        match self.upload.last_byte {
+            Some(last_byte) if last_byte <= first_byte => Some(first_byte - last_byte),
+            _ => Some(first_byte),
+        }
+    }
+
+    /// Encode the report as the bytes, first byte and last byte timestamps in microseconds of
+    /// the upload and the download as big-endian u64s, followed by the zero padded
+    /// implementation name.
+    pub fn encode(&self) -> [u8; Self::ENCODED_LEN] {
+        let timestamp =
+            |value: Option<Duration>| value.map_or(NO_TIMESTAMP, |value| value.as_micros() as u64);
+        let fields = [
+            self.upload.bytes,
+            timestamp(self.upload.first_byte),
+            timestamp(self.upload.last_byte),
+            self.download.bytes,
+            timestamp(self.download.first_byte),
+            timestamp(self.download.last_byte),
+        ];
+
+        let mut buf = [0u8; Self::ENCODED_LEN];
+        for (index, field) in fields.iter().enumerate() {
+            buf[index * 8..(index + 1) * 8].copy_from_slice(&field.to_be_bytes());
+        }
+        let name = self.implementation.as_bytes();
+        let len = name.len().min(IMPLEMENTATION_LEN);
+        buf[6 * 8..6 * 8 + len].copy_from_slice(&name[..len]);
+        buf
+    }
+
+    pub fn decode(buf: &[u8; Self::ENCODED_LEN]) -> Self {
+        let field = |index: usize| {
+            u64::from_be_bytes(buf[index * 8..(index + 1) * 8].try_into().expect("8 bytes"))
+        };
+        let timestamp = |index: usize| match field(index) {
+            NO_TIMESTAMP => None,
+            micros => Some(Duration::from_micros(micros)),
+        };
+        let name = &buf[6 * 8..];
+        let len = name
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(name.len());
+
+        Self {
+            implementation: String::from_utf8_lossy(&name[..len]).into_owned(),
+            upload: TransferReport {
+                bytes: field(0),
+                first_byte: timestamp(1),
+                last_byte: timestamp(2),
+            },
+            download: TransferReport {
+                bytes: field(3),
+                first_byte: timestamp(4),
+                last_byte: timestamp(5),
+            },
+        }
+    }
+}
+
+/// Log the client and the server view of a run side by side.
+///
+/// Timestamps are relative to the start of the run on each side, only durations compare
+/// across sides.
+pub fn log_side_by_side(client: &SideReport, server: &SideReport) {
+    let timestamp = |value: Option<Duration>| {
+        value.map_or("-".to_string(), |value| {
+            format!("{:.4}s", value.as_secs_f64())
+        })
+    };
+    let bandwidth = |report: &TransferReport| {
+        if report.duration().is_zero() {
+            "-".to_string()
+        } else {
+            Bandwidth::new(report.bytes, report.duration()).to_string()
+        }
+    };
+
+    tracing::info!("| Direction | Side | Bytes | First byte | Last byte | Duration | Bandwidth |");
+    tracing::info!("|-----------|------|-------|------------|-----------|----------|-----------|");
+    for (direction, client_view, server_view) in [
+        ("Upload", &client.upload, &server.upload),
+        ("Download", &client.download, &server.download),
+    ] {
+        for (side, implementation, report) in [
+            ("client", &client.implementation, client_view),
+            ("server", &server.implementation, server_view),
+        ] {
+            tracing::info!(
+                "| {direction} | {side} ({implementation}) | {} | {} | {} | {:.4}s | {} |",
+                ByteSize(report.bytes),
+                timestamp(report.first_byte),
+                timestamp(report.last_byte),
+                report.duration().as_secs_f64(),
+                bandwidth(report),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn sampler(interval: u64) -> Sampler {
+        Sampler {
+            interval: millis(interval),
+            direction: "Upload",
+            start: None,
+            bytes: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    fn sample(start: u64, end: u64, bytes: u64) -> IntervalSample {
+        IntervalSample {
+            start: millis(start),
+            end: millis(end),
+            bytes,
+        }
+    }
+
+    #[test]
+    fn sampler_splits_from_the_first_byte_and_keeps_stalls() {
+        let mut sampler = sampler(100);
+        sampler.record(millis(50), 10);
+        sampler.record(millis(120), 20);
+        // A chunk at the end of an interval belongs to the next one.
+        sampler.record(millis(150), 30);
+        // Nothing went through between 250ms and 350ms.
+        sampler.record(millis(380), 40);
+        sampler.finish(millis(400));
+
+        assert_eq!(
+            sampler.samples,
+            [
+                sample(50, 150, 30),
+                sample(150, 250, 30),
+                sample(250, 350, 0),
+                sample(350, 400, 40),
+            ]
+        );
+    }
+
+    #[test]
+    fn sampler_does_not_close_an_empty_last_interval() {
+        let mut sampler = sampler(100);
+        sampler.record(millis(0), 10);
+        sampler.record(millis(100), 0);
+        sampler.finish(millis(100));
+        assert_eq!(sampler.samples, [sample(0, 100, 10)]);
+    }
+
+    #[test]
+    fn side_report_round_trips() {
+        let report = SideReport {
+            implementation: "litep2p".to_string(),
+            upload: TransferReport {
+                bytes: 16 << 20,
+                first_byte: Some(Duration::from_micros(1)),
+                last_byte: Some(Duration::from_micros(1_234_567)),
+            },
+            download: TransferReport {
+                bytes: 0,
+                first_byte: None,
+                last_byte: None,
+            },
+        };
+        let encoded = report.encode();
+        assert_eq!(encoded.len(), SideReport::ENCODED_LEN);
+        assert_eq!(SideReport::decode(&encoded), report);
+    }
+
+    #[test]
+    fn side_report_truncates_long_implementation_names() {
+        let report = SideReport {
+            implementation: "an-implementation-name".to_string(),
+            ..SideReport::default()
+        };
+        let decoded = SideReport::decode(&report.encode());
+        assert_eq!(decoded.implementation, "an-implementatio");
+        assert_eq!(decoded.upload, report.upload);
+        assert_eq!(decoded.download, report.download);
+    }
+}
@@ -0,0 +1,449 @@
+use std::{path::Path, time::Duration};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ByteSize, IntervalSample, LatencyStats, ResourceUsage, RunParams, RunStats, TimedTransfer,
+    TransferOpts, TransportLayer, Workload,
+};
+
+/// Machine readable result of one client run, printed as a single JSON line with
+/// `--output json`.
+///
+/// Durations are in seconds and throughputs in bytes per second.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PerfResult {
+    /// Implementation of the client, `litep2p` or `libp2p`.
+    pub implementation: String,
+    /// Implementation of the server, if it reported the run.
+    pub server_implementation: Option<String>,
+    /// Transport layer of the connections.
+    pub transport: String,
+    /// Protocol requested by the client.
+    pub protocol: String,
+    /// What the run transfers, `bytes`, `duration` or `latency`.
+    pub mode: String,
+    /// Index of the run in the order the client executed them, `None` when the client failed
+    /// before reporting its runs.
+    pub run: Option<usize>,
+    /// The run is a warmup iteration, excluded from the summary statistics.
+    pub warmup: bool,
+    pub chunk_size: usize,
+    pub write_strategy: String,
+    pub verify: bool,
+    pub duplex: bool,
+    /// Substreams per connection running the transfer at the same time.
+    pub parallel: usize,
+    /// Connections running the transfer at the same time.
+    pub connections: usize,
+    pub upload: DirectionResult,
+    pub download: DirectionResult,
+    /// Wall time of a duplex run.
+    pub duplex_seconds: Option<f64>,
+    /// Round trip times of a latency run.
+    pub latency: Option<LatencyResult>,
+    /// How long the setup phases of the run took.
+    pub setup: SetupResult,
+    /// Resources used by the whole client process during the run, including the other
+    /// substreams and connections running at the same time.
+    #[serde(alias = "usage")]
+    pub process_usage: Option<UsageResult>,
+    /// Why the run failed, nothing is measured then.
+    pub error: Option<String>,
+}
+
+/// The parameters that identify the same measurement across sets of results.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Case {
+    pub client: String,
+    /// `None` when the server did not report its implementation.
+    pub server: Option<String>,
+    pub transport: String,
+    pub protocol: String,
+    pub mode: String,
+    pub upload: Requested,
+    pub download: Requested,
+    pub chunk_size: usize,
+    pub write_strategy: String,
+    pub verify: bool,
+    pub duplex: bool,
+    pub parallel: usize,
+    pub connections: usize,
+}
+
+/// What a run requested for one direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Requested {
+    Bytes(u64),
+    Milliseconds(u64),
+}
+
+impl Requested {
+    /// Nothing is transferred in the direction.
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Requested::Bytes(0) | Requested::Milliseconds(0))
+    }
+}
+
+impl std::fmt::Display for Requested {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Requested::Bytes(bytes) => write!(f, "{}", ByteSize(*bytes)),
+            Requested::Milliseconds(millis) => write!(f, "{}s", *millis as f64 / 1000.0),
+        }
+    }
+}
+
+impl std::fmt::Display for Case {
+    /// The implementation pair, the transport and the sizes, followed by the parameters that
+    /// differ from the defaults of the client.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let server = self.server.as_deref().unwrap_or("?");
+        write!(f, "{}->{server} {}", self.client, self.transport)?;
+        if self.protocol != "litep2p-perf" {
+            write!(f, " {}", self.protocol)?;
+        }
+        match self.mode.as_str() {
+            "latency" => write!(f, " latency {}", self.upload)?,
+            _ => write!(f, " {}/{}", self.upload, self.download)?,
+        }
+        if self.chunk_size != TransferOpts::default().chunk_size {
+            write!(f, " chunk {}", ByteSize(self.chunk_size as u64))?;
+        }
+        if self.write_strategy != "write-all" {
+            write!(f, " {}", self.write_strategy)?;
+        }
+        if self.verify {
+            write!(f, " verify")?;
+        }
+        if self.duplex {
+            write!(f, " duplex")?;
+        }
+        if self.parallel > 1 {
+            write!(f, " parallel {}", self.parallel)?;
+        }
+        if self.connections > 1 {
+            write!(f, " connections {}", self.connections)?;
+        }
+        Ok(())
+    }
+}
+
+/// Requested and measured transfer of one direction of a run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DirectionResult {
+    /// Requested bytes of a fixed size or latency run.
+    pub requested_bytes: Option<u64>,
+    /// Requested duration of a time bounded run.
+    pub requested_seconds: Option<f64>,
+    /// Transferred payload bytes.
+    pub bytes: u64,
+    pub seconds: f64,
+    pub bytes_per_second: f64,
+    /// Payload bytes received between the end of the ramp-up and the deadline of a time bounded run.
+    pub steady_bytes: Option<u64>,
+    /// Time between the end of the ramp-up and the deadline of a time bounded run.
+    pub steady_seconds: Option<f64>,
+    /// Steady-state throughput of a time bounded run, from `steady_bytes` and `steady_seconds`.
+    pub steady_bytes_per_second: Option<f64>,
+    /// Throughput sampled during the transfer, with `--interval`.
+    pub intervals: Vec<IntervalResult>,
+}
+
+/// Bytes transferred during one sampling interval.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct IntervalResult {
+    /// Start of the interval, relative to the start of the run.
+    pub start_seconds: f64,
+    /// End of the interval, relative to the start of the run.
+    pub end_seconds: f64,
+    pub bytes: u64,
+}
+
+/// Setup phases of a run, `None` when not measured.
+///
+/// The connection phases are only measured for the first run on a connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SetupResult {
+    pub connect_seconds: Option<f64>,
+    pub negotiation_seconds: Option<f64>,
+    pub security_seconds: Option<f64>,
+    pub muxer_seconds: Option<f64>,
+    pub connection_seconds: Option<f64>,
+    /// Only `connection_seconds` was measured, as litep2p does not expose the phases of its
+    /// connection setup.
+    #[serde(default)]
+    pub connection_total_only: bool,
+    pub substream_seconds: Option<f64>,
+    pub first_byte_seconds: Option<f64>,
+}
+
+/// CPU time, context switches and peak memory of the whole client process during a run.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct UsageResult {
+    pub wall_seconds: f64,
+    pub user_seconds: f64,
+    pub system_seconds: f64,
+    /// CPU time over wall time.
+    pub cpu_utilization: f64,
+    /// CPU seconds per GiB of payload in both directions, `None` when nothing was transferred.
+    pub cpu_seconds_per_gib: Option<f64>,
+    pub voluntary_context_switches: u64,
+    pub involuntary_context_switches: u64,
+    /// Peak resident set size of the process, see [`crate::ResourceUsage::peak_rss`].
+    pub peak_rss_bytes: u64,
+}
+
+/// Round trip time distribution of a latency run.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LatencyResult {
+    /// Number of echoed messages.
+    pub messages: usize,
+    pub min_seconds: f64,
+    pub p50_seconds: f64,
+    pub p90_seconds: f64,
+    pub p99_seconds: f64,
+    pub max_seconds: f64,
+}
+
+impl PerfResult {
+    /// The requested side of a run, without measurements.
+    pub fn new(
+        implementation: &str,
+        transport: TransportLayer,
+        params: &RunParams,
+        connections: usize,
+    ) -> Self {
+        let (mode, upload, download) = match params.workload {
+            Workload::Bytes { upload, download } => (
+                "bytes",
+                DirectionResult::requested_bytes(upload),
+                DirectionResult::requested_bytes(download),
+            ),
+            Workload::Duration { upload, download } => (
+                "duration",
+                DirectionResult::requested_seconds(upload.as_secs_f64()),
+                DirectionResult::requested_seconds(download.as_secs_f64()),
+            ),
+            Workload::Latency { messages, size } => (
+                "latency",
+                DirectionResult::requested_bytes(messages * size),
+                DirectionResult::requested_bytes(messages * size),
+            ),
+        };
+
+        Self {
+            implementation: implementation.to_string(),
+            server_implementation: None,
+            transport: value_name(transport),
+            protocol: value_name(params.protocol),
+            mode: mode.to_string(),
+            run: None,
+            warmup: false,
+            chunk_size: params.transfer.chunk_size,
+            write_strategy: value_name(params.transfer.write_strategy),
+            verify: params.verify,
+            duplex: params.duplex,
+            parallel: params.parallel,
+            connections,
+            upload,
+            download,
+            duplex_seconds: None,
+            latency: None,
+            setup: SetupResult::default(),
+            process_usage: None,
+            error: None,
+        }
+    }
+
+    /// Fill in the measurements of a completed run.
+    pub fn with_stats(mut self, stats: &RunStats) -> Self {
+        self.upload.measured(
+            stats.uploaded,
+            stats.upload_time.as_secs_f64(),
+            &stats.upload_intervals,
+        );
+        self.download.measured(
+            stats.downloaded,
+            stats.download_time.as_secs_f64(),
+            &stats.download_intervals,
+        );
+        if let Some(timed) = &stats.timed_upload {
+            self.upload.steady(timed);
+        }
+        if let Some(timed) = &stats.timed_download {
+            self.download.steady(timed);
+        }
+        self.server_implementation = stats
+            .server_report
+            .as_ref()
+            .map(|report| report.implementation.clone());
+        self.duplex_seconds = stats.duplex_time.map(|time| time.as_secs_f64());
+        self.latency = stats.latency.as_ref().map(LatencyResult::new);
+        self.setup = SetupResult::new(stats);
+        self.process_usage = stats
+            .usage
+            .map(|usage| UsageResult::new(&usage, stats.uploaded + stats.downloaded));
+        self
+    }
+
+    /// Record why the run failed.
+    pub fn with_error(mut self, error: &dyn std::fmt::Display) -> Self {
+        self.error = Some(error.to_string());
+        self
+    }
+
+    /// Print the result as a single JSON line on the standard output.
+    pub fn print(&self) {
+        println!(
+            "{}",
+            serde_json::to_string(self).expect("results serialize to JSON")
+        );
+    }
+
+    /// The case the run measures.
+    pub fn case(&self) -> Case {
+        Case {
+            client: self.implementation.clone(),
+            server: self.server_implementation.clone(),
+            transport: self.transport.clone(),
+            protocol: self.protocol.clone(),
+            mode: self.mode.clone(),
+            upload: self.upload.requested(),
+            download: self.download.requested(),
+            chunk_size: self.chunk_size,
+            write_strategy: self.write_strategy.clone(),
+            verify: self.verify,
+            duplex: self.duplex,
+            parallel: self.parallel,
+            connections: self.connections,
+        }
+    }
+
+    /// Read the results printed by clients into a file, one JSON object per line.
+    ///
+    /// Lines that do not hold a JSON object, such as log lines, are skipped.
+    pub fn load(path: &Path) -> std::io::Result<Vec<PerfResult>> {
+        let content = std::fs::read_to_string(path)?;
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| line.trim_start().starts_with('{'))
+            .map(|(index, line)| {
+                serde_json::from_str(line).map_err(|error| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("{}:{}: {error}", path.display(), index + 1),
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+impl DirectionResult {
+    /// What the run requested, bytes or a duration.
+    pub fn requested(&self) -> Requested {
+        match (self.requested_bytes, self.requested_seconds) {
+            (Some(bytes), _) => Requested::Bytes(bytes),
+            (None, Some(seconds)) => Requested::Milliseconds((seconds * 1000.0).round() as u64),
+            (None, None) => Requested::Bytes(0),
+        }
+    }
+
+    /// Throughput to compare runs by, the steady-state throughput of a time bounded run.
+    pub fn throughput(&self) -> f64 {
+        self.steady_bytes_per_second
+            .unwrap_or(self.bytes_per_second)
+    }
+
+    fn requested_bytes(bytes: u64) -> Self {
+        Self {
+            requested_bytes: Some(bytes),
+            ..Default::default()
+        }
+    }
+
+    fn requested_seconds(seconds: f64) -> Self {
+        Self {
+            requested_seconds: Some(seconds),
+            ..Default::default()
+        }
+    }
+
+    fn measured(&mut self, bytes: u64, seconds: f64, intervals: &[IntervalSample]) {
+        self.bytes = bytes;
+        self.seconds = seconds;
+        self.bytes_per_second = bytes as f64 / seconds.max(f64::EPSILON);
+        self.intervals = intervals
+            .iter()
+            .map(|sample| IntervalResult {
+                start_seconds: sample.start.as_secs_f64(),
+                end_seconds: sample.end.as_secs_f64(),
+                bytes: sample.bytes,
+            })
+            .collect();
+    }
+
+    fn steady(&mut self, timed: &TimedTransfer) {
+        let seconds = timed.steady_time.as_secs_f64();
+        self.steady_bytes = Some(timed.steady_bytes);
+        self.steady_seconds = Some(seconds);
+        self.steady_bytes_per_second = Some(timed.steady_bytes as f64 / seconds.max(f64::EPSILON));
+    }
+}
+
+impl SetupResult {
+    fn new(stats: &RunStats) -> Self {
+        let seconds = |time: Option<Duration>| time.map(|time| time.as_secs_f64());
+        let connection = stats.connection_setup.unwrap_or_default();
+        Self {
+            connect_seconds: seconds(connection.connect),
+            negotiation_seconds: seconds(connection.negotiation),
+            security_seconds: seconds(connection.security),
+            muxer_seconds: seconds(connection.muxer),
+            connection_seconds: seconds(connection.total),
+            connection_total_only: connection.total_only,
+            substream_seconds: seconds(stats.substream_setup),
+            first_byte_seconds: seconds(stats.first_byte),
+        }
+    }
+}
+
+impl UsageResult {
+    fn new(usage: &ResourceUsage, bytes: u64) -> Self {
+        Self {
+            wall_seconds: usage.wall_time.as_secs_f64(),
+            user_seconds: usage.user_time.as_secs_f64(),
+            system_seconds: usage.system_time.as_secs_f64(),
+            cpu_utilization: usage.cpu_utilization(),
+            cpu_seconds_per_gib: usage.cpu_seconds_per_gib(bytes),
+            voluntary_context_switches: usage.voluntary_switches,
+            involuntary_context_switches: usage.involuntary_switches,
+            peak_rss_bytes: usage.peak_rss,
+        }
+    }
+}
+
+impl LatencyResult {
+    fn new(latency: &LatencyStats) -> Self {
+        Self {
+            messages: latency.rtts.len(),
+            min_seconds: latency.min().as_secs_f64(),
+            p50_seconds: latency.percentile(50.0).as_secs_f64(),
+            p90_seconds: latency.percentile(90.0).as_secs_f64(),
+            p99_seconds: latency.percentile(99.0).as_secs_f64(),
+            max_seconds: latency.max().as_secs_f64(),
+        }
+    }
+}
+
+/// The command line name of a value.
+fn value_name<T: ValueEnum>(value: T) -> String {
+    value
+        .to_possible_value()
+        .expect("no skipped variants")
+        .get_name()
+        .to_string()
+}
@@ -0,0 +1,113 @@
+use std::time::{Duration, Instant};
+
+/// Step of the setup of a client connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetupEvent {
+    /// The client started dialing the server.
+    Dial,
+    /// The TCP or WebSocket connection is established.
+    Connected,
+    /// multistream-select agreed on the security protocol.
+    SecurityNegotiated,
+    /// The noise handshake completed.
+    Secured,
+    /// multistream-select agreed on the multiplexer.
+    MuxerNegotiated,
+    /// The stack reported the connection as established.
+    Established,
+}
+
+/// When each step of the setup of a connection happened, as far as the stack exposes it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SetupTimeline {
+    dial: Option<Instant>,
+    connected: Option<Instant>,
+    security_negotiated: Option<Instant>,
+    secured: Option<Instant>,
+    muxer_negotiated: Option<Instant>,
+    established: Option<Instant>,
+}
+
+impl SetupTimeline {
+    pub const fn new() -> Self {
+        Self {
+            dial: None,
+            connected: None,
+            security_negotiated: None,
+            secured: None,
+            muxer_negotiated: None,
+            established: None,
+        }
+    }
+
+    /// Record that the step happened at `now`, keeping the first occurrence of each step.
+    pub fn mark(&mut self, event: SetupEvent, now: Instant) {
+        let slot = match event {
+            SetupEvent::Dial => &mut self.dial,
+            SetupEvent::Connected => &mut self.connected,
+            SetupEvent::SecurityNegotiated => &mut self.security_negotiated,
+            SetupEvent::Secured => &mut self.secured,
+            SetupEvent::MuxerNegotiated => &mut self.muxer_negotiated,
+            SetupEvent::Established => &mut self.established,
+        };
+        slot.get_or_insert(now);
+    }
+
+    /// The duration of each phase whose start and end were both recorded.
+    pub fn phases(&self) -> ConnectionSetup {
+        let between = |start: Option<Instant>, end: Option<Instant>| {
+            Some(end?.saturating_duration_since(start?))
+        };
+        let security_negotiation = between(self.connected, self.security_negotiated);
+        let muxer_negotiation = between(self.secured, self.muxer_negotiated);
+        let total = between(self.dial, self.established);
+        let steps = [
+            self.connected,
+            self.security_negotiated,
+            self.secured,
+            self.muxer_negotiated,
+        ];
+
+        ConnectionSetup {
+            connect: between(self.dial, self.connected),
+            negotiation: security_negotiation
+                .zip(muxer_negotiation)
+                .map(|(security, muxer)| security + muxer),
+            security: between(self.security_negotiated, self.secured),
+            muxer: between(self.muxer_negotiated, self.established),
+            total,
+            total_only: total.is_some() && steps.iter().all(Option::is_none),
+        }
+    }
+}
+
+/// How long each phase of the setup of a connection took.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionSetup {
+    /// Dial until the TCP or WebSocket connection is established.
+    pub connect: Option<Duration>,
+    /// multistream-select negotiation of the security protocol and of the multiplexer.
+    pub negotiation: Option<Duration>,
+    /// The noise handshake.
+    pub security: Option<Duration>,
+    /// Multiplexer setup, until the stack reported the connection.
+    pub muxer: Option<Duration>,
+    /// Dial until the stack reported the connection.
+    pub total: Option<Duration>,
+    /// Only `total` was measured, the stack does not expose the steps in between.
+    pub total_only: bool,
+}
+
+impl ConnectionSetup {
+    /// The longest of each phase of two connections.
+    pub fn longest(self, other: Self) -> Self {
+        Self {
+            connect: self.connect.max(other.connect),
+            negotiation: self.negotiation.max(other.negotiation),
+            security: self.security.max(other.security),
+            muxer: self.muxer.max(other.muxer),
+            total: self.total.max(other.total),
+            total_only: self.total_only && other.total_only,
+        }
+    }
+}
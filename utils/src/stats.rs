@@ -0,0 +1,740 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    Bandwidth, ByteSize, ConnectionSetup, IntervalSample, ResourceUsage, RunParams, SideReport,
+};
+
+/// Measurements of a single client run.
+#[derive(Debug, Clone, Default)]
+pub struct RunStats {
+    /// Bytes uploaded to the server.
+    pub uploaded: u64,
+    /// Time spent uploading, until the server acknowledged the upload if it did.
+    pub upload_time: Duration,
+    /// Time until the last upload byte was handed to the substream, if the upload time was
+    /// taken from the server acknowledgement.
+    pub upload_send_time: Option<Duration>,
+    /// Bytes downloaded from the server.
+    pub downloaded: u64,
+    /// Time spent downloading.
+    pub download_time: Duration,
+    /// Wall time of a duplex run, from the start of both directions until both completed.
+    pub duplex_time: Option<Duration>,
+    /// Receiver side measurements of a time bounded upload.
+    pub timed_upload: Option<TimedTransfer>,
+    /// Receiver side measurements of a time bounded download.
+    pub timed_download: Option<TimedTransfer>,
+    /// The client view of the run, reported next to the server view.
+    pub client_report: Option<SideReport>,
+    /// The server view of the run, if the server reported it.
+    pub server_report: Option<SideReport>,
+    /// Round trip times of a latency run.
+    pub latency: Option<LatencyStats>,
+    /// Upload throughput sampled by the client during the run, if sampling was requested.
+    pub upload_intervals: Vec<IntervalSample>,
+    /// Download throughput sampled by the client during the run, if sampling was requested.
+    pub download_intervals: Vec<IntervalSample>,
+    /// Setup of the connection, for the first run on the connection.
+    pub connection_setup: Option<ConnectionSetup>,
+    /// Time to open the substream and negotiate the perf protocol on it.
+    pub substream_setup: Option<Duration>,
+    /// Time to the first byte of the download, see [`SideReport::time_to_first_byte`].
+    pub first_byte: Option<Duration>,
+    /// Resources used by the client process during the run.
+    pub usage: Option<ResourceUsage>,
+    /// Measurements of each substream of a run over parallel substreams.
+    ///
+    /// The other fields then hold the aggregate of all substreams.
+    pub substreams: Vec<RunStats>,
+    /// Measurements of each connection of a run over several connections.
+    ///
+    /// The other fields then hold the aggregate of all connections.
+    pub connections: Vec<RunStats>,
+}
+
+impl RunStats {
+    /// Aggregate the measurements of substreams that ran at the same time.
+    ///
+    /// Byte counts are summed and times are the longest of any substream. The throughput
+    /// samples are merged interval by interval and the client and server reports are merged
+    /// with [`SideReport::merge`], each substream keeps its own in [`RunStats::substreams`].
+    /// The measurements of a single substream are returned as is.
+    pub fn aggregate(mut substreams: Vec<RunStats>) -> Self {
+        if substreams.len() == 1 {
+            return substreams.pop().expect("one substream");
+        }
+
+        let mut stats = Self::sum(&substreams);
+        stats.substreams = substreams;
+        stats
+    }
+
+    /// Aggregate the measurements of connections that ran at the same time, like
+    /// [`RunStats::aggregate`] does for substreams.
+    pub fn aggregate_connections(mut connections: Vec<RunStats>) -> Self {
+        if connections.len() == 1 {
+            return connections.pop().expect("one connection");
+        }
+
+        let mut stats = Self::sum(&connections);
+        stats.connections = connections;
+        stats
+    }
+
+    /// Aggregate the runs of connections that ran the same runs, run by run.
+    pub fn aggregate_runs(connections: Vec<Vec<RunStats>>) -> Vec<Self> {
+        let num_runs = connections.iter().map(Vec::len).min().unwrap_or(0);
+        (0..num_runs)
+            .map(|index| {
+                Self::aggregate_connections(
+                    connections
+                        .iter()
+                        .map(|connection| connection[index].clone())
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn sum(parts: &[RunStats]) -> Self {
+        let mut stats = RunStats::default();
+        for part in parts {
+            stats.uploaded += part.uploaded;
+            stats.upload_time = stats.upload_time.max(part.upload_time);
+            stats.upload_send_time = stats.upload_send_time.max(part.upload_send_time);
+            stats.downloaded += part.downloaded;
+            stats.download_time = stats.download_time.max(part.download_time);
+            stats.duplex_time = stats.duplex_time.max(part.duplex_time);
+            stats.timed_upload =
+                combine(stats.timed_upload, part.timed_upload, TimedTransfer::merge);
+            stats.timed_download = combine(
+                stats.timed_download,
+                part.timed_download,
+                TimedTransfer::merge,
+            );
+            stats.client_report = combine(
+                stats.client_report.take(),
+                part.client_report.clone(),
+                SideReport::merge,
+            );
+            stats.server_report = combine(
+                stats.server_report.take(),
+                part.server_report.clone(),
+                SideReport::merge,
+            );
+            merge_intervals(&mut stats.upload_intervals, &part.upload_intervals);
+            merge_intervals(&mut stats.download_intervals, &part.download_intervals);
+            stats.connection_setup = combine(
+                stats.connection_setup,
+                part.connection_setup,
+                ConnectionSetup::longest,
+            );
+            stats.substream_setup = stats.substream_setup.max(part.substream_setup);
+            stats.first_byte = stats.first_byte.max(part.first_byte);
+            stats.usage = combine(stats.usage, part.usage, ResourceUsage::longest);
+        }
+        let rtts: Vec<_> = parts
+            .iter()
+            .filter_map(|part| part.latency.as_ref())
+            .flat_map(|latency| latency.rtts.iter().copied())
+            .collect();
+        if !rtts.is_empty() {
+            stats.latency = Some(LatencyStats::new(rtts));
+        }
+        stats
+    }
+}
+
+/// Combine two optional measurements, keeping either one when the other is missing.
+fn combine<T>(value: Option<T>, other: Option<T>, merge: impl FnOnce(T, T) -> T) -> Option<T> {
+    match (value, other) {
+        (Some(value), Some(other)) => Some(merge(value, other)),
+        (value, other) => value.or(other),
+    }
+}
+
+/// Add the throughput samples of a part that ran at the same time, interval by interval.
+///
+/// Each part samples from its own first byte, the merged intervals span the intervals of the
+/// same index of every part.
+fn merge_intervals(merged: &mut Vec<IntervalSample>, samples: &[IntervalSample]) {
+    for (index, sample) in samples.iter().enumerate() {
+        match merged.get_mut(index) {
+            Some(interval) => {
+                interval.start = interval.start.min(sample.start);
+                interval.end = interval.end.max(sample.end);
+                interval.bytes += sample.bytes;
+            }
+            None => merged.push(*sample),
+        }
+    }
+}
+
+/// Summary statistics of the throughput of repeated iterations of a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ThroughputSummary {
+    /// Arithmetic mean, in bits per second.
+    pub mean: f64,
+    /// Sample standard deviation, in bits per second.
+    pub std_dev: f64,
+    /// Slowest iteration, in bits per second.
+    pub min: f64,
+    /// Median iteration, in bits per second.
+    pub median: f64,
+    /// Fastest iteration, in bits per second.
+    pub max: f64,
+}
+
+impl ThroughputSummary {
+    /// Summarize the throughputs of the iterations, given in bits per second.
+    pub fn new(throughputs: &[f64]) -> Self {
+        if throughputs.is_empty() {
+            return Self::default();
+        }
+        let mut sorted = throughputs.to_vec();
+        sorted.sort_by(f64::total_cmp);
+
+        let n = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / n as f64;
+        let variance = if n > 1 {
+            sorted
+                .iter()
+                .map(|value| (value - mean).powi(2))
+                .sum::<f64>()
+                / (n - 1) as f64
+        } else {
+            0.0
+        };
+        let median = if n.is_multiple_of(2) {
+            (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+        } else {
+            sorted[n / 2]
+        };
+
+        Self {
+            mean,
+            std_dev: variance.sqrt(),
+            min: sorted[0],
+            median,
+            max: sorted[n - 1],
+        }
+    }
+
+    /// Summarize the upload throughput of the iterations.
+    pub fn upload(iterations: &[RunStats]) -> Self {
+        let throughputs: Vec<_> = iterations
+            .iter()
+            .map(|stats| Bandwidth::new(stats.uploaded, stats.upload_time).bits_per_second())
+            .collect();
+        Self::new(&throughputs)
+    }
+
+    /// Summarize the download throughput of the iterations.
+    pub fn download(iterations: &[RunStats]) -> Self {
+        let throughputs: Vec<_> = iterations
+            .iter()
+            .map(|stats| Bandwidth::new(stats.downloaded, stats.download_time).bits_per_second())
+            .collect();
+        Self::new(&throughputs)
+    }
+}
+
+/// Log summary statistics of the throughput of the measured iterations of a run.
+pub fn log_iterations(iterations: &[RunStats]) {
+    tracing::info!("Iterations n={}:", iterations.len());
+    tracing::info!("| Direction | Mean | Std dev | Min | Median | Max |");
+    tracing::info!("|-----------|------|---------|-----|--------|-----|");
+    for (direction, summary) in [
+        ("Upload", ThroughputSummary::upload(iterations)),
+        ("Download", ThroughputSummary::download(iterations)),
+    ] {
+        tracing::info!(
+            "| {direction} | {} | {} | {} | {} | {} |",
+            Bandwidth::from_bits_per_second(summary.mean),
+            Bandwidth::from_bits_per_second(summary.std_dev),
+            Bandwidth::from_bits_per_second(summary.min),
+            Bandwidth::from_bits_per_second(summary.median),
+            Bandwidth::from_bits_per_second(summary.max),
+        );
+    }
+}
+
+/// Log how long the setup phases of a run took, leaving out the phases that were not measured.
+pub fn log_setup(stats: &RunStats) {
+    let setup = stats.connection_setup.unwrap_or_default();
+    let phases: Vec<_> = [
+        ("connect", setup.connect),
+        ("negotiation", setup.negotiation),
+        ("security", setup.security),
+        ("muxer", setup.muxer),
+        (
+            if setup.total_only {
+                "connection (total only)"
+            } else {
+                "connection"
+            },
+            setup.total,
+        ),
+        ("substream", stats.substream_setup),
+        ("first byte", stats.first_byte),
+    ]
+    .into_iter()
+    .filter_map(|(phase, time)| Some(format!("{phase} {:?}", time?)))
+    .collect();
+    if !phases.is_empty() {
+        tracing::info!("Setup {}", phases.join(" "));
+    }
+}
+
+/// Round trip times of the echoed messages of a latency run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    /// Round trip time of each message, sorted from the fastest to the slowest.
+    pub rtts: Vec<Duration>,
+}
+
+impl LatencyStats {
+    pub fn new(mut rtts: Vec<Duration>) -> Self {
+        rtts.sort_unstable();
+        Self { rtts }
+    }
+
+    pub fn min(&self) -> Duration {
+        self.rtts.first().copied().unwrap_or_default()
+    }
+
+    pub fn max(&self) -> Duration {
+        self.rtts.last().copied().unwrap_or_default()
+    }
+
+    /// Nearest-rank percentile of the round trip times, `percent` is in `0..=100`.
+    pub fn percentile(&self, percent: f64) -> Duration {
+        if self.rtts.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (percent / 100.0 * self.rtts.len() as f64).ceil() as usize;
+        self.rtts[rank.clamp(1, self.rtts.len()) - 1]
+    }
+}
+
+/// Log the round trip time distribution of a latency run.
+pub fn log_latency(latency: &LatencyStats) {
+    tracing::info!(
+        "Round trip times n={} min {:?} p50 {:?} p90 {:?} p99 {:?} max {:?}",
+        latency.rtts.len(),
+        latency.min(),
+        latency.percentile(50.0),
+        latency.percentile(90.0),
+        latency.percentile(99.0),
+        latency.max(),
+    );
+}
+
+/// Measurements of a time bounded transfer, taken by the receiving side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimedTransfer {
+    /// Total payload bytes moved, including the bytes in flight when the sender was stopped.
+    pub bytes: u64,
+    /// Payload bytes received between the first byte and the deadline.
+    pub bytes_at_deadline: u64,
+    /// Time between the first byte and the deadline.
+    pub elapsed: Duration,
+    /// Payload bytes received between the end of the ramp-up and the deadline.
+    pub steady_bytes: u64,
+    /// Time between the end of the ramp-up and the deadline.
+    pub steady_time: Duration,
+}
+
+impl TimedTransfer {
+    /// Length of the encoded stop message.
+    pub const ENCODED_LEN: usize = 32;
+
+    /// Encode the measurements taken at the deadline into the stop message sent to the sender.
+    pub fn encode(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut buf = [0u8; Self::ENCODED_LEN];
+        buf[..8].copy_from_slice(&self.bytes_at_deadline.to_be_bytes());
+        buf[8..16].copy_from_slice(&(self.elapsed.as_micros() as u64).to_be_bytes());
+        buf[16..24].copy_from_slice(&self.steady_bytes.to_be_bytes());
+        buf[24..].copy_from_slice(&(self.steady_time.as_micros() as u64).to_be_bytes());
+        buf
+    }
+
+    /// Combine the measurements of substreams or connections that ran at the same time: the
+    /// bytes are summed and the times are the longest of either.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            bytes: self.bytes + other.bytes,
+            bytes_at_deadline: self.bytes_at_deadline + other.bytes_at_deadline,
+            elapsed: self.elapsed.max(other.elapsed),
+            steady_bytes: self.steady_bytes + other.steady_bytes,
+            steady_time: self.steady_time.max(other.steady_time),
+        }
+    }
+
+    /// Decode a stop message, `bytes` is the total number of bytes written by the sender.
+    pub fn decode(buf: &[u8; Self::ENCODED_LEN], bytes: u64) -> Self {
+        let field = |index: usize| {
+            u64::from_be_bytes(buf[index * 8..(index + 1) * 8].try_into().expect("8 bytes"))
+        };
+        Self {
+            bytes,
+            bytes_at_deadline: field(0),
+            elapsed: Duration::from_micros(field(1)),
+            steady_bytes: field(2),
+            steady_time: Duration::from_micros(field(3)),
+        }
+    }
+}
+
+/// Share of the duration excluded from the steady-state throughput.
+const RAMP_UP_DIVISOR: u32 = 10;
+
+/// Tracks the deadline of a time bounded transfer on the receiving side.
+#[derive(Debug)]
+pub struct TimedMeter {
+    duration: Duration,
+    bytes: u64,
+    first: Option<Instant>,
+    ramp_up: Option<(Instant, u64)>,
+    deadline: Option<TimedTransfer>,
+}
+
+impl TimedMeter {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            bytes: 0,
+            first: None,
+            ramp_up: None,
+            deadline: None,
+        }
+    }
+
+    /// Record `len` received bytes.
+    ///
+    /// Returns the measurements to send to the sender once the deadline has passed.
+    pub fn record(&mut self, len: usize) -> Option<TimedTransfer> {
+        let now = Instant::now();
+        let first = *self.first.get_or_insert(now);
+        self.bytes += len as u64;
+
+        if self.deadline.is_some() {
+            return None;
+        }
+
+        let elapsed = now.duration_since(first);
+        // The ramp-up ends with the first chunk received after it.
+        if self.ramp_up.is_none() && elapsed >= self.duration / RAMP_UP_DIVISOR {
+            self.ramp_up = Some((now, self.bytes));
+        }
+        if elapsed < self.duration {
+            return None;
+        }
+
+        let (ramp_up, ramp_up_bytes) = self.ramp_up.unwrap_or((first, 0));
+        let timed = TimedTransfer {
+            bytes: self.bytes,
+            bytes_at_deadline: self.bytes,
+            elapsed,
+            steady_bytes: self.bytes - ramp_up_bytes,
+            steady_time: now.duration_since(ramp_up),
+        };
+        self.deadline = Some(timed);
+        Some(timed)
+    }
+
+    /// The measurements of the transfer, `None` if the deadline was never reached.
+    pub fn finish(self) -> Option<TimedTransfer> {
+        self.deadline.map(|timed| TimedTransfer {
+            bytes: self.bytes,
+            ..timed
+        })
+    }
+}
+
+/// Log the measurements of a time bounded transfer.
+pub fn log_timed(direction: &str, total_time: Duration, timed: &TimedTransfer) {
+    tracing::info!(
+        "{direction} {} bytes in {:.4}s, {} before the deadline of {:.4}s bandwidth {} steady-state bandwidth {}",
+        ByteSize(timed.bytes),
+        total_time.as_secs_f64(),
+        ByteSize(timed.bytes_at_deadline),
+        timed.elapsed.as_secs_f64(),
+        Bandwidth::new(timed.bytes_at_deadline, timed.elapsed),
+        Bandwidth::new(timed.steady_bytes, timed.steady_time),
+    );
+}
+
+/// Log the per direction and the combined throughput of a duplex run.
+pub fn log_duplex(stats: &RunStats, duplex_time: Duration) {
+    tracing::info!(
+        "Uploaded {} bytes in {:.4}s bandwidth {} (duplex)",
+        ByteSize(stats.uploaded),
+        stats.upload_time.as_secs_f64(),
+        Bandwidth::new(stats.uploaded, stats.upload_time),
+    );
+    tracing::info!(
+        "Downloaded {} bytes in {:.4}s bandwidth {} (duplex)",
+        ByteSize(stats.downloaded),
+        stats.download_time.as_secs_f64(),
+        Bandwidth::new(stats.downloaded, stats.download_time),
+    );
+    let combined = stats.uploaded + stats.downloaded;
+    tracing::info!(
+        "Combined {} bytes in {:.4}s bandwidth {}",
+        ByteSize(combined),
+        duplex_time.as_secs_f64(),
+        Bandwidth::new(combined, duplex_time),
+    );
+}
+
+/// Jain's fairness index of the given throughputs.
+///
+/// The index is 1 when all substreams get the same throughput and `1 / n` when a single
+/// one gets everything.
+pub fn fairness_index(throughputs: &[f64]) -> f64 {
+    let sum: f64 = throughputs.iter().sum();
+    let sum_of_squares: f64 = throughputs.iter().map(|value| value * value).sum();
+    if sum_of_squares == 0.0 {
+        return 1.0;
+    }
+    sum * sum / (throughputs.len() as f64 * sum_of_squares)
+}
+
+/// Log the per substream and the aggregate throughput of a run over parallel substreams.
+pub fn log_parallel(stats: &RunStats) {
+    tracing::info!("Parallel substreams n={}:", stats.substreams.len());
+    log_shares("Substream", &stats.substreams, stats);
+}
+
+/// Log the per connection and the aggregate throughput of a run over several connections.
+pub fn log_connections(stats: &RunStats) {
+    tracing::info!("Connections n={}:", stats.connections.len());
+    log_shares("Connection", &stats.connections, stats);
+}
+
+/// Log the throughput of each part of an aggregated run and the fairness between them.
+fn log_shares(label: &str, parts: &[RunStats], aggregate: &RunStats) {
+    let throughput =
+        |bytes: u64, time: Duration| bytes as f64 / time.as_secs_f64().max(f64::EPSILON);
+    let uploads: Vec<_> = parts
+        .iter()
+        .map(|part| throughput(part.uploaded, part.upload_time))
+        .collect();
+    let downloads: Vec<_> = parts
+        .iter()
+        .map(|part| throughput(part.downloaded, part.download_time))
+        .collect();
+
+    tracing::info!("| {label} | Upload | Download |");
+    tracing::info!("|-----------|--------|----------|");
+    for (index, part) in parts.iter().enumerate() {
+        tracing::info!(
+            "| {} | {} | {} |",
+            index + 1,
+            Bandwidth::new(part.uploaded, part.upload_time),
+            Bandwidth::new(part.downloaded, part.download_time),
+        );
+    }
+    tracing::info!(
+        "| Aggregate | {} | {} |",
+        Bandwidth::new(aggregate.uploaded, aggregate.upload_time),
+        Bandwidth::new(aggregate.downloaded, aggregate.download_time),
+    );
+    tracing::info!(
+        "Fairness index upload {:.4} download {:.4}",
+        fairness_index(&uploads),
+        fairness_index(&downloads),
+    );
+}
+
+/// Log the throughput of each run of a chunk size sweep, the mean of the measured iterations.
+pub fn log_sweep(runs: &[(RunParams, Vec<RunStats>)]) {
+    tracing::info!("Chunk size sweep:");
+    tracing::info!("| Chunk size | Write strategy | Upload | Download |");
+    tracing::info!("|------------|----------------|--------|----------|");
+    for (params, iterations) in runs {
+        tracing::info!(
+            "| {} | {:?} | {} | {} |",
+            ByteSize(params.transfer.chunk_size as u64),
+            params.transfer.write_strategy,
+            Bandwidth::from_bits_per_second(ThroughputSummary::upload(iterations).mean),
+            Bandwidth::from_bits_per_second(ThroughputSummary::download(iterations).mean),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::TransferReport;
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn substream(bytes: u64, last_byte: u64, intervals: &[(u64, u64, u64)]) -> RunStats {
+        let report = TransferReport {
+            bytes,
+            first_byte: Some(millis(1)),
+            last_byte: Some(millis(last_byte)),
+        };
+        RunStats {
+            uploaded: bytes,
+            upload_time: millis(last_byte),
+            upload_send_time: Some(millis(last_byte - 1)),
+            server_report: Some(SideReport {
+                implementation: "libp2p".to_string(),
+                upload: report,
+                download: TransferReport::default(),
+            }),
+            upload_intervals: intervals
+                .iter()
+                .map(|&(start, end, bytes)| IntervalSample {
+                    start: millis(start),
+                    end: millis(end),
+                    bytes,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn aggregate_merges_intervals_and_reports() {
+        let stats = RunStats::aggregate(vec![
+            substream(300, 250, &[(1, 101, 100), (101, 201, 150), (201, 250, 50)]),
+            substream(200, 180, &[(2, 102, 120), (102, 180, 80)]),
+        ]);
+
+        assert_eq!(stats.uploaded, 500);
+        assert_eq!(stats.upload_time, millis(250));
+        assert_eq!(stats.upload_send_time, Some(millis(249)));
+        let intervals: Vec<_> = stats
+            .upload_intervals
+            .iter()
+            .map(|sample| (sample.start, sample.end, sample.bytes))
+            .collect();
+        assert_eq!(
+            intervals,
+            [
+                (millis(1), millis(102), 220),
+                (millis(101), millis(201), 230),
+                (millis(201), millis(250), 50),
+            ]
+        );
+
+        let server_report = stats.server_report.unwrap();
+        assert_eq!(server_report.implementation, "libp2p");
+        assert_eq!(server_report.upload.bytes, 500);
+        assert_eq!(server_report.upload.first_byte, Some(millis(1)));
+        assert_eq!(server_report.upload.last_byte, Some(millis(250)));
+        assert_eq!(stats.substreams.len(), 2);
+        assert!(stats.substreams[1].server_report.is_some());
+    }
+
+    #[test]
+    fn aggregate_merges_timed_transfers() {
+        let timed = |bytes, elapsed| TimedTransfer {
+            bytes,
+            bytes_at_deadline: bytes - 10,
+            elapsed: millis(elapsed),
+            steady_bytes: bytes / 2,
+            steady_time: millis(elapsed / 2),
+        };
+        let stats = RunStats::aggregate(vec![
+            RunStats {
+                timed_upload: Some(timed(100, 1000)),
+                ..Default::default()
+            },
+            RunStats {
+                timed_upload: Some(timed(300, 1002)),
+                ..Default::default()
+            },
+        ]);
+
+        assert_eq!(
+            stats.timed_upload,
+            Some(TimedTransfer {
+                bytes: 400,
+                bytes_at_deadline: 380,
+                elapsed: millis(1002),
+                steady_bytes: 200,
+                steady_time: millis(501),
+            })
+        );
+        assert_eq!(stats.timed_download, None);
+    }
+
+    #[test]
+    fn latency_percentiles_use_the_nearest_rank() {
+        let latency = LatencyStats::new((1..=10).rev().map(millis).collect());
+
+        assert_eq!(latency.min(), millis(1));
+        assert_eq!(latency.max(), millis(10));
+        assert_eq!(latency.percentile(0.0), millis(1));
+        assert_eq!(latency.percentile(10.0), millis(1));
+        assert_eq!(latency.percentile(10.1), millis(2));
+        assert_eq!(latency.percentile(50.0), millis(5));
+        assert_eq!(latency.percentile(99.0), millis(10));
+        assert_eq!(latency.percentile(100.0), millis(10));
+        assert_eq!(LatencyStats::default().percentile(50.0), Duration::ZERO);
+    }
+
+    #[test]
+    fn aggregate_pools_round_trip_times() {
+        let latency = |rtts: &[u64]| RunStats {
+            latency: Some(LatencyStats::new(
+                rtts.iter().copied().map(millis).collect(),
+            )),
+            ..Default::default()
+        };
+        let stats = RunStats::aggregate(vec![latency(&[3, 1]), latency(&[2, 4])]);
+
+        assert_eq!(
+            stats.latency.unwrap().rtts,
+            [millis(1), millis(2), millis(3), millis(4)]
+        );
+    }
+
+    #[test]
+    fn timed_meter_measures_up_to_the_deadline() {
+        let mut meter = TimedMeter::new(millis(50));
+        assert_eq!(meter.record(100), None);
+        std::thread::sleep(millis(10));
+        // The first chunk after the ramp-up of 5ms ends it.
+        assert_eq!(meter.record(200), None);
+        assert_eq!(meter.record(300), None);
+        std::thread::sleep(millis(50));
+
+        let timed = meter.record(400).expect("the deadline passed");
+        assert_eq!(timed.bytes_at_deadline, 1000);
+        assert_eq!(timed.steady_bytes, 700);
+        assert!(timed.elapsed >= millis(50));
+        assert!(timed.steady_time < timed.elapsed);
+
+        // The bytes in flight after the deadline only count in the total.
+        assert_eq!(meter.record(500), None);
+        assert_eq!(
+            meter.finish(),
+            Some(TimedTransfer {
+                bytes: 1500,
+                ..timed
+            })
+        );
+        assert_eq!(TimedMeter::new(millis(50)).finish(), None);
+    }
+
+    #[test]
+    fn timed_transfer_round_trips_with_the_sender_total() {
+        let timed = TimedTransfer {
+            bytes: 2000,
+            bytes_at_deadline: 1500,
+            elapsed: Duration::from_micros(1_000_123),
+            steady_bytes: 1000,
+            steady_time: Duration::from_micros(900_456),
+        };
+        assert_eq!(TimedTransfer::decode(&timed.encode(), 2000), timed);
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+    time::Duration,
+};
+
+use clap::ValueEnum;
+
+/// Prefixes used to print byte sizes and bandwidths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum UnitSystem {
+    /// Decimal prefixes, `kB` and `Mbit/s` are 1000 and 1000000, like iperf and the libp2p perf
+    /// tools.
+    #[clap(name = "si")]
+    Si,
+
+    /// Binary prefixes, `KiB` and `Mibit/s` are 1024 and 1048576.
+    #[clap(name = "iec")]
+    Iec,
+}
+
+/// The prefixes of the process, set once from the command line.
+static UNITS: AtomicU8 = AtomicU8::new(UnitSystem::Si as u8);
+
+/// Print all byte sizes and bandwidths of the process with `units`.
+pub fn set_units(units: UnitSystem) {
+    UNITS.store(units as u8, Ordering::Relaxed);
+}
+
+/// The prefixes byte sizes and bandwidths are printed with.
+pub fn units() -> UnitSystem {
+    match UNITS.load(Ordering::Relaxed) {
+        value if value == UnitSystem::Iec as u8 => UnitSystem::Iec,
+        _ => UnitSystem::Si,
+    }
+}
+
+impl UnitSystem {
+    pub(crate) fn base(self) -> f64 {
+        match self {
+            UnitSystem::Si => 1000.0,
+            UnitSystem::Iec => 1024.0,
+        }
+    }
+
+    /// The largest prefix below `value`, with `value` scaled to it.
+    fn scale(self, value: f64) -> (f64, &'static str) {
+        const SI: [&str; 5] = ["", "k", "M", "G", "T"];
+        const IEC: [&str; 5] = ["", "Ki", "Mi", "Gi", "Ti"];
+        let prefixes = match self {
+            UnitSystem::Si => SI,
+            UnitSystem::Iec => IEC,
+        };
+
+        let mut value = value;
+        let mut index = 0;
+        while value >= self.base() && index + 1 < prefixes.len() {
+            value /= self.base();
+            index += 1;
+        }
+        (value, prefixes[index])
+    }
+}
+
+/// Multiplier of a prefix, decimal without `i` and binary with it, whatever the case.
+fn multiplier(prefix: &str) -> Option<f64> {
+    let (power, binary) = match prefix.to_ascii_lowercase().as_str() {
+        "" => (0, false),
+        "k" => (1, false),
+        "ki" => (1, true),
+        "m" => (2, false),
+        "mi" => (2, true),
+        "g" => (3, false),
+        "gi" => (3, true),
+        "t" => (4, false),
+        "ti" => (4, true),
+        _ => return None,
+    };
+    let base: f64 = if binary { 1024.0 } else { 1000.0 };
+    Some(base.powi(power))
+}
+
+/// Split `value` into its number and what follows it.
+fn split_number(value: &str) -> Result<(f64, &str), String> {
+    let value = value.trim();
+    let end = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let number = value[..end]
+        .parse::<f64>()
+        .map_err(|_| format!("invalid number in `{value}`"))?;
+    Ok((number, value[end..].trim()))
+}
+
+/// A number of bytes.
+///
+/// Parses from a number followed by an optional prefix and `B`, such as `1024`, `16MiB`,
+/// `1G` or `1.5 kB`, which must be a whole number of bytes. Prefixes without `i` are decimal and prefixes with it are binary. A
+/// lowercase `b`, the symbol of bits, is rejected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ByteSize(pub u64);
+
+impl ByteSize {
+    /// Format with the given prefixes instead of the ones of the process.
+    pub fn format(self, units: UnitSystem) -> String {
+        match units.scale(self.0 as f64) {
+            (value, "") => format!("{value} B"),
+            (value, prefix) => format!("{value:.2} {prefix}B"),
+        }
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format(units()))
+    }
+}
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (number, unit) = split_number(value)?;
+        // A lowercase `b` is a bit, as in `Mb`, and would read as a size eight times too large.
+        if unit.ends_with('b') {
+            return Err(format!("`{unit}` is a number of bits, use `B` for bytes"));
+        }
+        let prefix = unit.strip_suffix('B').unwrap_or(unit);
+        let multiplier = multiplier(prefix).ok_or_else(|| format!("unknown byte unit `{unit}`"))?;
+        let scaled = number * multiplier;
+        let bytes = scaled.round();
+        // Leave room for the rounding error of the decimal number and of the product, so that
+        // `1.1MB` is still a whole number of bytes.
+        if (scaled - bytes).abs() > scaled * 4.0 * f64::EPSILON {
+            return Err(format!("`{value}` is not a whole number of bytes"));
+        }
+        // `u64::MAX as f64` rounds up to 2^64, the first size that does not fit.
+        if bytes >= u64::MAX as f64 {
+            return Err(format!("`{value}` is too large"));
+        }
+        Ok(ByteSize(bytes as u64))
+    }
+}
+
+impl From<u64> for ByteSize {
+    fn from(bytes: u64) -> Self {
+        ByteSize(bytes)
+    }
+}
+
+/// A throughput.
+///
+/// Parses from a number followed by an optional prefix and `bit/s` or `bps`, such as
+/// `100Mbit/s` or `1G`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+pub struct Bandwidth {
+    bits_per_second: f64,
+}
+
+impl Bandwidth {
+    /// The throughput of `bytes` transferred in `time`.
+    pub fn new(bytes: u64, time: Duration) -> Self {
+        Self::from_bits_per_second(bytes as f64 * 8.0 / time.as_secs_f64().max(f64::EPSILON))
+    }
+
+    pub fn from_bits_per_second(bits_per_second: f64) -> Self {
+        Self { bits_per_second }
+    }
+
+    pub fn bits_per_second(self) -> f64 {
+        self.bits_per_second
+    }
+
+    /// Format with the given prefixes instead of the ones of the process.
+    pub fn format(self, units: UnitSystem) -> String {
+        let (value, prefix) = units.scale(self.bits_per_second);
+        format!("{value:.2} {prefix}bit/s")
+    }
+}
+
+impl fmt::Display for Bandwidth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format(units()))
+    }
+}
+
+impl FromStr for Bandwidth {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (number, unit) = split_number(value)?;
+        let prefix = unit
+            .strip_suffix("bit/s")
+            .or_else(|| unit.strip_suffix("bps"))
+            .unwrap_or(unit);
+        let multiplier =
+            multiplier(prefix).ok_or_else(|| format!("unknown bandwidth unit `{unit}`"))?;
+        Ok(Self::from_bits_per_second(number * multiplier))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_sizes_parse_decimal_and_binary_prefixes() {
+        assert_eq!("1024".parse(), Ok(ByteSize(1024)));
+        assert_eq!("16MiB".parse(), Ok(ByteSize(16 * 1024 * 1024)));
+        assert_eq!("16M".parse(), Ok(ByteSize(16_000_000)));
+        assert_eq!("1.5 kB".parse(), Ok(ByteSize(1500)));
+        assert_eq!("1gi".parse(), Ok(ByteSize(1 << 30)));
+        assert_eq!("2TB".parse(), Ok(ByteSize(2_000_000_000_000)));
+        assert_eq!("1.1MB".parse(), Ok(ByteSize(1_100_000)));
+        assert_eq!("0.3GB".parse(), Ok(ByteSize(300_000_000)));
+        assert_eq!("16777215TiB".parse(), Ok(ByteSize(16_777_215 * (1 << 40))));
+
+        assert!("16Mb".parse::<ByteSize>().is_err());
+        assert!("16 bytes".parse::<ByteSize>().is_err());
+        assert!("MiB".parse::<ByteSize>().is_err());
+
+        assert!("1.5".parse::<ByteSize>().is_err());
+        assert!("1.0001kB".parse::<ByteSize>().is_err());
+        assert!("1000000000.5".parse::<ByteSize>().is_err());
+        assert!("16777216TiB".parse::<ByteSize>().is_err());
+        assert!("18446744073709551616".parse::<ByteSize>().is_err());
+    }
+
+    #[test]
+    fn byte_sizes_format_with_the_given_prefixes() {
+        assert_eq!(ByteSize(999).format(UnitSystem::Si), "999 B");
+        assert_eq!(ByteSize(1500).format(UnitSystem::Si), "1.50 kB");
+        assert_eq!(ByteSize(1500).format(UnitSystem::Iec), "1.46 KiB");
+        assert_eq!(ByteSize(16 << 20).format(UnitSystem::Iec), "16.00 MiB");
+        assert_eq!(ByteSize(16 << 20).format(UnitSystem::Si), "16.78 MB");
+    }
+
+    #[test]
+    fn bandwidths_parse_and_format() {
+        assert_eq!(
+            "100Mbit/s".parse::<Bandwidth>().unwrap().bits_per_second(),
+            100e6
+        );
+        assert_eq!("1Gbps".parse::<Bandwidth>().unwrap().bits_per_second(), 1e9);
+        assert_eq!(
+            "1Mibit/s".parse::<Bandwidth>().unwrap().bits_per_second(),
+            1048576.0
+        );
+        assert!("1 MB/s".parse::<Bandwidth>().is_err());
+
+        let bandwidth = Bandwidth::new(1_000_000, Duration::from_secs(1));
+        assert_eq!(bandwidth.format(UnitSystem::Si), "8.00 Mbit/s");
+        assert_eq!(bandwidth.format(UnitSystem::Iec), "7.63 Mibit/s");
+    }
+}
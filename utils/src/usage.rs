@@ -0,0 +1,149 @@
+use std::{
+    sync::Once,
+    time::{Duration, Instant},
+};
+
+use crate::ByteSize;
+
+const GIB: f64 = (1u64 << 30) as f64;
+
+/// CPU time, context switches and peak memory of the process over a run or a server session.
+///
+/// The figures are process wide: runs, sessions and connections that overlap in time are all
+/// accounted to each of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// Wall time the usage was measured over.
+    pub wall_time: Duration,
+    /// CPU time spent in user space.
+    pub user_time: Duration,
+    /// CPU time spent in the kernel.
+    pub system_time: Duration,
+    /// Context switches because the process waited, for I/O or a lock.
+    pub voluntary_switches: u64,
+    /// Context switches because the scheduler preempted the process.
+    pub involuntary_switches: u64,
+    /// Peak resident set size of the process in bytes, since the first measurement when the
+    /// kernel allows resetting it and since the start of the process otherwise.
+    pub peak_rss: u64,
+}
+
+impl ResourceUsage {
+    pub fn cpu_time(&self) -> Duration {
+        self.user_time + self.system_time
+    }
+
+    /// CPU time over wall time, above 1 when several cores were busy.
+    pub fn cpu_utilization(&self) -> f64 {
+        self.cpu_time().as_secs_f64() / self.wall_time.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// CPU seconds spent per GiB of payload, `None` when nothing was transferred.
+    pub fn cpu_seconds_per_gib(&self, bytes: u64) -> Option<f64> {
+        (bytes > 0).then(|| self.cpu_time().as_secs_f64() / (bytes as f64 / GIB))
+    }
+
+    /// The largest of each figure of two runs that overlapped in time.
+    pub fn longest(self, other: Self) -> Self {
+        Self {
+            wall_time: self.wall_time.max(other.wall_time),
+            user_time: self.user_time.max(other.user_time),
+            system_time: self.system_time.max(other.system_time),
+            voluntary_switches: self.voluntary_switches.max(other.voluntary_switches),
+            involuntary_switches: self.involuntary_switches.max(other.involuntary_switches),
+            peak_rss: self.peak_rss.max(other.peak_rss),
+        }
+    }
+}
+
+/// Measures the [`ResourceUsage`] of the process from its start on.
+#[derive(Debug, Clone, Copy)]
+pub struct UsageMeter {
+    start: Instant,
+    usage: ResourceUsage,
+}
+
+impl UsageMeter {
+    /// Start measuring, resetting the peak resident set size of the process on the first call.
+    ///
+    /// The peak is only reset once so that concurrent measurements do not reset each other's.
+    pub fn start() -> Self {
+        static RESET_PEAK_RSS: Once = Once::new();
+        // Writing 5 to `clear_refs` resets the peak RSS reported in `/proc/self/status`.
+        RESET_PEAK_RSS.call_once(|| {
+            let _ = std::fs::write("/proc/self/clear_refs", "5");
+        });
+        Self {
+            start: Instant::now(),
+            usage: process_usage(),
+        }
+    }
+
+    /// The usage since the start.
+    pub fn finish(&self) -> ResourceUsage {
+        let usage = process_usage();
+        ResourceUsage {
+            wall_time: self.start.elapsed(),
+            user_time: usage.user_time.saturating_sub(self.usage.user_time),
+            system_time: usage.system_time.saturating_sub(self.usage.system_time),
+            voluntary_switches: usage
+                .voluntary_switches
+                .saturating_sub(self.usage.voluntary_switches),
+            involuntary_switches: usage
+                .involuntary_switches
+                .saturating_sub(self.usage.involuntary_switches),
+            peak_rss: usage.peak_rss,
+        }
+    }
+}
+
+/// The usage of the process since it started, zero when `getrusage` fails.
+fn process_usage() -> ResourceUsage {
+    // SAFETY: `rusage` is plain old data and `getrusage` only writes to the given struct.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return ResourceUsage::default();
+    }
+    let time = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+
+    ResourceUsage {
+        wall_time: Duration::ZERO,
+        user_time: time(usage.ru_utime),
+        system_time: time(usage.ru_stime),
+        voluntary_switches: usage.ru_nvcsw as u64,
+        involuntary_switches: usage.ru_nivcsw as u64,
+        // `ru_maxrss` is in KiB and cannot be reset.
+        peak_rss: peak_rss().unwrap_or(usage.ru_maxrss as u64 * 1024),
+    }
+}
+
+/// The `VmHWM` of `/proc/self/status`, in bytes.
+fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim();
+    kib.parse::<u64>().ok().map(|kib| kib * 1024)
+}
+
+/// Log the usage of the whole process during a run or a session that transferred `bytes` of
+/// payload.
+pub fn log_usage(label: &str, usage: &ResourceUsage, bytes: u64) {
+    let per_gib = usage
+        .cpu_seconds_per_gib(bytes)
+        .map_or("-".to_string(), |seconds| format!("{seconds:.4}s"));
+    tracing::info!(
+        "{label} process CPU user {:.4}s system {:.4}s utilization {:.2} per GiB {per_gib} context switches voluntary {} involuntary {} peak RSS {}",
+        usage.user_time.as_secs_f64(),
+        usage.system_time.as_secs_f64(),
+        usage.cpu_utilization(),
+        usage.voluntary_switches,
+        usage.involuntary_switches,
+        ByteSize(usage.peak_rss),
+    );
+}
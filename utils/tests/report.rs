@@ -0,0 +1,67 @@
+//! Golden-file tests of the output of the report generator.
+//!
+//! Run with `UPDATE_GOLDEN=1` to regenerate the files in `tests/golden` after an intended change
+//! of the output format.
+
+use std::path::{Path, PathBuf};
+
+use utils::{BandwidthReport, Metric, PerfResult, UnitSystem};
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+fn report() -> BandwidthReport {
+    let results = PerfResult::load(&tests_dir().join("data/results.jsonl")).unwrap();
+    let mut reports = BandwidthReport::new(&results);
+    assert_eq!(reports.len(), 1, "one report per transport");
+    reports.remove(0)
+}
+
+fn assert_golden(name: &str, actual: &str) {
+    let path = tests_dir().join("golden").join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|error| panic!("failed to read {}: {error}", path.display()));
+    assert!(
+        actual == expected,
+        "{name} differs from its golden file, rerun with UPDATE_GOLDEN=1 if the change is \
+         intended\n--- expected\n{expected}\n--- actual\n{actual}"
+    );
+}
+
+#[test]
+fn markdown() {
+    assert_golden(
+        "report.md",
+        &report().markdown(UnitSystem::Iec, Some("images")),
+    );
+}
+
+#[test]
+fn markdown_si() {
+    assert_golden("report-si.md", &report().markdown(UnitSystem::Si, None));
+}
+
+#[test]
+fn upload_chart() {
+    let report = report();
+    assert_eq!(report.chart_name(Metric::Upload), "tcp-upload.svg");
+    assert_golden(
+        "tcp-upload.svg",
+        &report.chart(Metric::Upload, UnitSystem::Iec),
+    );
+}
+
+#[test]
+fn download_chart() {
+    let report = report();
+    assert_eq!(report.chart_name(Metric::Download), "tcp-download.svg");
+    assert_golden(
+        "tcp-download.svg",
+        &report.chart(Metric::Download, UnitSystem::Iec),
+    );
+}